@@ -0,0 +1,205 @@
+use super::{
+    driver, migrations::MigrationStep, script, Driver, DriverResult, Error, MigrationTemplate,
+    NamedDriver, RunnerConfiguration, RunnerMeta,
+};
+use indoc::indoc;
+use log::info;
+
+const DEFAULT_INTERPRETER: &str = "bash";
+
+/// Runs `.sh`, `.bash3` and `.bash4` migrations, e.g. data backfills which
+/// shell out to other tools. The rendered script is written to a temporary
+/// file, and run with the configured `interpreter` (`bash` by default) as
+/// `<interpreter> <file> <path>`, so the migration's path is `$1`,
+/// with the rest of the configuration exported as `MITRE_*` environment
+/// variables, and killed if it runs for longer than its timeout (see
+/// `script::timeout`.)
+pub struct Bash {
+    config: RunnerConfiguration,
+}
+
+// The configuration as environment variables, only those which are set.
+fn environment(config: &RunnerConfiguration) -> Vec<(&'static str, String)> {
+    let mut env = vec![("MITRE_DRIVER", config._driver.clone())];
+    let optional = [
+        ("MITRE_DATABASE", config.database.clone()),
+        (
+            "MITRE_DATABASE_NUMBER",
+            config.database_number.map(|n| n.to_string()),
+        ),
+        ("MITRE_INDEX", config.index.clone()),
+        ("MITRE_HOST", config.ip_or_hostname.clone()),
+        ("MITRE_PORT", config.port.map(|p| p.to_string())),
+        ("MITRE_USERNAME", config.username.clone()),
+        ("MITRE_PASSWORD", config.password.clone()),
+        ("MITRE_PATH", config.path.clone()),
+        ("MITRE_PROTOCOL", config.protocol.clone()),
+    ];
+    for (name, value) in std::array::IntoIter::new(optional) {
+        if let Some(value) = value {
+            env.push((name, value));
+        }
+    }
+    env
+}
+
+impl Bash {
+    pub fn new(config: RunnerConfiguration) -> Result<Self, Error> {
+        Ok(Bash { config })
+    }
+
+    fn template_ctx(&self) -> mustache::Data {
+        mustache::MapBuilder::new()
+            .insert_str("database", self.config.database.clone().unwrap_or_default())
+            .insert_str(
+                "ip_or_hostname",
+                self.config.ip_or_hostname.clone().unwrap_or_default(),
+            )
+            .build()
+    }
+
+    fn render_script(&self, script: &str) -> Result<String, Error> {
+        driver::render_template(script, &self.template_ctx(), "script")
+    }
+}
+
+impl Driver for Bash {
+    fn run(&mut self, ms: &MigrationStep) -> Result<DriverResult, Error> {
+        let script = self.render_script(&ms.source)?;
+        // The interpreter may come with arguments of its own, e.g "bash -eu"
+        let interpreter = self
+            .config
+            .interpreter
+            .clone()
+            .unwrap_or_else(|| String::from(DEFAULT_INTERPRETER));
+        let file = script::script_file(&script)?;
+        let mut command = script::command(&interpreter);
+        command
+            .arg(file.path())
+            .arg(&ms.path)
+            .envs(environment(&self.config));
+
+        info!("running {} with {}", ms.path.display(), interpreter);
+        let timeout = script::timeout(&ms.source, self.config.timeout)?;
+        let (stdout, stderr) = script::run(command, &ms.path, None {}, timeout)?;
+        script::log_output(&ms.path, &stdout, &stderr);
+        Ok(DriverResult::Success)
+    }
+
+    fn render(&self, ms: &MigrationStep) -> Result<String, Error> {
+        self.render_script(&ms.source)
+    }
+
+    fn migration_template(&self) -> MigrationTemplate {
+        MigrationTemplate {
+            extension: "sh",
+            up: indoc!(
+                r#"
+                # Put your migration here, the configuration is in $MITRE_DATABASE,
+                # $MITRE_HOST and friends
+                echo "migrating $MITRE_DATABASE"
+                "#
+            ),
+            down: Some(indoc!(
+                r#"
+                echo "unmigrating $MITRE_DATABASE"
+                "#
+            )),
+        }
+    }
+
+    fn meta(&self) -> RunnerMeta<'static> {
+        // Bash3 and Bash4 are the same driver, configured by either name
+        let name =
+            match self.config._driver.to_lowercase() == crate::reserved::BASH_3.to_lowercase() {
+                true => crate::reserved::BASH_3,
+                false => crate::reserved::BASH_4,
+            };
+        crate::reserved::runner_by_name(name).expect("reserved word not found")
+    }
+}
+
+impl NamedDriver for Bash {
+    fn name() -> &'static str {
+        "bash"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn config() -> RunnerConfiguration {
+        RunnerConfiguration {
+            _driver: String::from("bash4"),
+            database: Some(String::from("analytics")),
+            ip_or_hostname: Some(String::from("127.0.0.1")),
+            port: Some(5432),
+            ..Default::default()
+        }
+    }
+
+    fn step(source: &str) -> MigrationStep {
+        MigrationStep {
+            path: PathBuf::from("/foo/20210503213400_backfill.analytics.bash4"),
+            source: String::from(source),
+        }
+    }
+
+    #[test]
+    fn exports_the_configuration_to_the_script() -> Result<(), String> {
+        let mut bash = Bash::new(config()).map_err(|e| format!("{:?}", e))?;
+        bash.run(&step(
+            r#"test "$MITRE_DATABASE:$MITRE_HOST:$MITRE_PORT" = "analytics:127.0.0.1:5432" && test -z "${MITRE_USERNAME+x}""#,
+        ))
+        .map(|_| ())
+        .map_err(|e| format!("{:?}", e))
+    }
+
+    #[test]
+    fn fails_with_the_exit_code_and_output() -> Result<(), String> {
+        let mut bash = Bash::new(config()).map_err(|e| format!("{:?}", e))?;
+        match bash.run(&step("echo backfilling; echo no such table >&2; exit 3")) {
+            Err(Error::ScriptFailed {
+                exit_code,
+                stdout,
+                stderr,
+                ..
+            }) => {
+                assert_eq!(exit_code, Some(3));
+                assert_eq!(stdout, "backfilling\n");
+                assert_eq!(stderr, "no such table\n");
+                Ok(())
+            }
+            Err(e) => Err(format!("unexpected error {:?}", e)),
+            Ok(_) => Err(String::from("a non-zero exit was treated as success")),
+        }
+    }
+
+    #[test]
+    fn runs_the_configured_interpreter_with_its_arguments() -> Result<(), String> {
+        let mut bash = Bash::new(RunnerConfiguration {
+            interpreter: Some(String::from("sh -u")),
+            ..config()
+        })
+        .map_err(|e| format!("{:?}", e))?;
+        match bash.run(&step("echo $UNSET_IN_MITRE_TESTS")) {
+            Err(Error::ScriptFailed { .. }) => Ok(()),
+            Err(e) => Err(format!("unexpected error {:?}", e)),
+            Ok(_) => Err(String::from("-u was not passed to the interpreter")),
+        }
+    }
+
+    #[test]
+    fn runs_scripts_longer_than_an_argument_may_be() -> Result<(), String> {
+        let mut bash = Bash::new(config()).map_err(|e| format!("{:?}", e))?;
+        let script = format!(
+            "# {}\ntest \"$1\" = /foo/20210503213400_backfill.analytics.bash4",
+            "x".repeat(200 * 1024)
+        );
+        bash.run(&step(&script))
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e))
+    }
+}
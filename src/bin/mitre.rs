@@ -1,15 +1,17 @@
-use clap::{crate_authors, App, Arg};
+use clap::{crate_authors, App, Arg, ArgMatches};
 use log::{error, info, trace};
 use std::path::Path;
+use std::time::Duration;
 use tabular::{Row, Table};
 
 #[cfg(feature = "ui")]
 use mitre::ui::start_web_ui;
 
 use mitre::{
-    config, migration_list_from_disk, migration_storage_from_config, migrations, reserved,
-    runner_from_config, Configuration, Direction, Engine, MigrationList, MigrationResultTuple,
-    MigrationStorage,
+    config, driver_from_config, migration_list_from_disk, migration_storage_from_config,
+    migrations, reserved, ApplyOptions, Configuration, Direction, Engine, HistoryEntry,
+    MigrationList, MigrationResult, MigrationResultTuple, MigrationStorage, PlannedMigration,
+    TagFilter, Target, DEFAULT_LOCK_TIMEOUT,
 };
 
 fn main() {
@@ -20,6 +22,42 @@ fn main() {
 
     trace!("starting");
 
+    let include_tags = Arg::new("include_tags")
+        .long("include-tags")
+        .takes_value(true)
+        .value_name("TAGS")
+        .about("Comma separated tags to include (data, long and risky are excluded by default)");
+    let exclude_tags = Arg::new("exclude_tags")
+        .long("exclude-tags")
+        .takes_value(true)
+        .value_name("TAGS")
+        .about("Comma separated tags to exclude in addition to the default data, long and risky");
+
+    let to = Arg::new("to")
+        .long("to")
+        .takes_value(true)
+        .value_name("VERSION")
+        .conflicts_with_all(&["steps", "only"])
+        .about("Migrate up to and including, or roll back to (but not including) this timestamp");
+    let steps = Arg::new("steps")
+        .long("steps")
+        .takes_value(true)
+        .value_name("N")
+        .conflicts_with_all(&["to", "only"])
+        .about("Migrate the N oldest pending, or roll back the N newest applied migrations");
+    let only = Arg::new("only")
+        .long("only")
+        .takes_value(true)
+        .value_name("VERSION")
+        .conflicts_with_all(&["to", "steps"])
+        .about("Migrate, or roll back, only the migration with this timestamp");
+
+    let lock_timeout = Arg::new("lock_timeout")
+        .long("lock-timeout")
+        .takes_value(true)
+        .value_name("SECONDS")
+        .about("How long to wait for a concurrent run to release the migration lock (default 60)");
+
     let m = App::new("mitre")
         .version("0.1")
         .author(crate_authors!("\n"))
@@ -47,10 +85,95 @@ fn main() {
                 .subcommand(App::new("ls").about("list reserved words")),
         )
         .subcommand(App::new("ui").about("starts the web-based UI"))
-        .subcommand(App::new("ls").about("list all migrations and their status"))
+        .subcommand(
+            App::new("ls")
+                .about("list all migrations and their status")
+                .arg(include_tags.clone())
+                .arg(exclude_tags.clone()),
+        )
         .subcommand(App::new("up").about("deprecated, use migrate"))
-        .subcommand(App::new("migrate").about("run all outstanding migrations"))
-        .subcommand(App::new("down").about("reverse all reversible migrations"))
+        .subcommand(
+            App::new("migrate")
+                .about("run all outstanding migrations")
+                .arg(include_tags.clone())
+                .arg(exclude_tags.clone())
+                .arg(to.clone())
+                .arg(steps.clone())
+                .arg(only.clone())
+                .arg(lock_timeout.clone())
+                .arg(
+                    Arg::new("continue_on_error")
+                        .long("continue-on-error")
+                        .about("Keep running migrations after one has failed (only for independent configurations)"),
+                )
+                .arg(
+                    Arg::new("strict")
+                        .long("strict")
+                        .about("Refuse to run while any applied migration has been modified on disk"),
+                ),
+        )
+        .subcommand(
+            App::new("down")
+                .about("reverse all reversible migrations")
+                .arg(to.clone())
+                .arg(steps.clone())
+                .arg(only.clone())
+                .arg(lock_timeout.clone())
+                .arg(
+                    Arg::new("continue_on_error")
+                        .long("continue-on-error")
+                        .about("Keep reversing migrations after one has failed (only for independent configurations)"),
+                ),
+        )
+        .subcommand(
+            App::new("plan")
+                .about("show the rendered steps migrate (or down) would run, without running them")
+                .arg(include_tags)
+                .arg(exclude_tags)
+                .arg(to)
+                .arg(steps)
+                .arg(only)
+                .arg(
+                    Arg::new("down")
+                        .long("down")
+                        .about("Plan reversing migrations, as down would"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .about("Print the plan as JSON"),
+                ),
+        )
+        .subcommand(
+            App::new("redo")
+                .about("reverse and re-run the newest (or a given) migration")
+                .arg(
+                    Arg::new("only")
+                        .long("only")
+                        .takes_value(true)
+                        .value_name("VERSION")
+                        .about("Redo the migration with this timestamp instead of the newest applied one"),
+                )
+                .arg(lock_timeout),
+        )
+        .subcommand(
+            App::new("history")
+                .about("list every recorded attempt to apply or reverse a migration")
+                .arg(
+                    Arg::new("only")
+                        .long("only")
+                        .takes_value(true)
+                        .value_name("VERSION")
+                        .about("Only list attempts of the migration with this timestamp"),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .long("limit")
+                        .takes_value(true)
+                        .value_name("N")
+                        .about("Only list the N most recent attempts"),
+                ),
+        )
         .subcommand(App::new("show-migrations").about("for migrations"))
         .subcommand(
             App::new("generate-migration")
@@ -167,7 +290,7 @@ mitre --help
 
         Some("reserved-words") => {
             let mut table = Table::new("{:<} {:<} {:<} {:<}");
-            reserved::words().iter().for_each(|word| {
+            reserved::words_and_plugins().iter().for_each(|word| {
                 match word {
                     reserved::ReservedWord::Runner(r) => table.add_row(
                         Row::new()
@@ -204,7 +327,11 @@ mitre --help
 
             // TODO: return something from error_code module in this crate
             // TODO: sort the migrations, list somehow
-            match Engine::diff(migration_list(&config), migration_storage(&config)) {
+            match Engine::diff(
+                migration_list(&config),
+                migration_storage(&config),
+                &tag_filter(m.subcommand_matches("ls")),
+            ) {
                 Err(e) => error!("Error: {:?}", e),
                 Ok(migrations) => {
                     for (migration_state, m) in migrations {
@@ -241,47 +368,126 @@ mitre --help
             std::process::exit(1);
         }
 
-        Some("migrate") => match apply(&config, Some(vec![&Direction::Up])) {
+        Some("migrate") => match apply(
+            &config,
+            Some(vec![&Direction::Up]),
+            apply_options(m.subcommand_matches("migrate")),
+        ) {
+            Err(mitre::Error::LockTimeout { timeout }) => exit_lock_timeout(timeout),
+            Err(mitre::Error::ModifiedMigrations { versions }) => {
+                error!(
+                    "Refusing to migrate, applied migrations were modified since: {}",
+                    versions.join(", ")
+                );
+                std::process::exit(1);
+            }
             Err(e) => {
                 error!("Error applying migrations (direction: up): {:?}", e);
                 std::process::exit(124);
             }
-            Ok(r) => {
-                let mut table = Table::new("{:>}  {:<}");
-                for (result, migration) in r {
-                    table.add_row(
-                        Row::new().with_cell(format!("{:?}", result)).with_cell(
-                            migration
-                                .date_time
-                                .format(crate::migrations::FORMAT_STR)
-                                .to_string(),
-                        ),
-                    );
-                }
-                print!("{}", table);
-            }
+            Ok(r) => print_results(r),
         },
 
-        Some("down") => match apply(&config, Some(vec![&Direction::Down])) {
+        Some("down") => match apply(
+            &config,
+            Some(vec![&Direction::Down]),
+            apply_options(m.subcommand_matches("down")),
+        ) {
+            Err(mitre::Error::LockTimeout { timeout }) => exit_lock_timeout(timeout),
             Err(e) => {
                 error!("Error applying migrations (direction: down): {:?}", e);
                 std::process::exit(124);
             }
-            Ok(r) => {
-                let mut table = Table::new("{:>}  {:<}");
-                for (result, migration) in r {
+            Ok(r) => print_results(r),
+        },
+
+        Some("plan") => {
+            let sub_m = m.subcommand_matches("plan");
+            let direction = match sub_m.map(|sub_m| sub_m.is_present("down")) {
+                Some(true) => Direction::Down,
+                _ => Direction::Up,
+            };
+            match Engine::plan(
+                &config,
+                migration_list(&config),
+                migration_storage(&config),
+                Some(vec![&direction]),
+                // Nothing is run or locked, only which migrations would be matters
+                &ApplyOptions {
+                    tags: tag_filter(sub_m),
+                    target: target(sub_m),
+                    ..ApplyOptions::default()
+                },
+            ) {
+                Err(e) => {
+                    error!(
+                        "Error planning migrations (direction: {:?}): {:?}",
+                        direction, e
+                    );
+                    std::process::exit(124);
+                }
+                Ok(plan) => match sub_m.map(|sub_m| sub_m.is_present("json")) {
+                    Some(true) => println!("{}", plan_to_json(&plan)),
+                    _ => print_plan(&plan),
+                },
+            }
+        }
+
+        Some("history") => {
+            let sub_m = m
+                .subcommand_matches("history")
+                .expect("expected to match subcommand");
+            let version = version_arg(sub_m, "only");
+            let limit = match sub_m.value_of("limit").map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) => n,
+                Some(Err(e)) => {
+                    error!("--limit must be a positive number: {}", e);
+                    std::process::exit(1);
+                }
+                None => usize::MAX,
+            };
+            match migration_storage(&config).history() {
+                Err(e) => {
+                    error!("Error reading the migration history: {:?}", e);
+                    std::process::exit(124);
+                }
+                Ok(history) => {
+                    let history = history
+                        .into_iter()
+                        .filter(|entry| match &version {
+                            Some(version) => *version == entry.version,
+                            None => true,
+                        })
+                        .collect::<Vec<HistoryEntry>>();
+                    let mut table = Table::new("{:<}  {:<}  {:<}  {:>}  {:<}  {:<}  {:<}  {:<}");
                     table.add_row(
-                        Row::new().with_cell(format!("{:?}", result)).with_cell(
-                            migration
-                                .date_time
-                                .format(crate::migrations::FORMAT_STR)
-                                .to_string(),
-                        ),
+                        Row::new()
+                            .with_cell("Started")
+                            .with_cell("Timestamp")
+                            .with_cell("Direction")
+                            .with_cell("Duration")
+                            .with_cell("Outcome")
+                            .with_cell("Host")
+                            .with_cell("Mitre")
+                            .with_cell("Error"),
                     );
+                    for entry in history.iter().skip(history.len().saturating_sub(limit)) {
+                        table.add_row(
+                            Row::new()
+                                .with_cell(entry.started_at.to_rfc3339())
+                                .with_cell(&entry.version)
+                                .with_cell(format!("{:?}", entry.direction))
+                                .with_cell(format!("{}ms", entry.duration().num_milliseconds()))
+                                .with_cell(&entry.outcome)
+                                .with_cell(&entry.hostname)
+                                .with_cell(&entry.mitre_version)
+                                .with_cell(entry.error.as_deref().unwrap_or("")),
+                        );
+                    }
+                    print!("{}", table);
                 }
-                print!("{}", table);
             }
-        },
+        }
 
         Some("ui") => {
             #[cfg(not(feature = "ui"))]
@@ -308,7 +514,53 @@ mitre --help
                 }
             }
         }
-        Some("redo") => {} // redo was used
+        Some("redo") => {
+            let version = m
+                .subcommand_matches("redo")
+                .and_then(|sub_m| version_arg(sub_m, "only"));
+            match Engine::redo(
+                &config,
+                migration_list(&config),
+                migration_storage(&config),
+                version,
+                lock_timeout_arg(m.subcommand_matches("redo")),
+            ) {
+                Err(mitre::Error::LockTimeout { timeout }) => exit_lock_timeout(timeout),
+                Err(mitre::Error::IrreversibleMigration { version }) => {
+                    error!(
+                        "Cannot redo {}, it is irreversible (it has no down step)",
+                        version
+                    );
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    error!("Error redoing migration: {:?}", e);
+                    std::process::exit(124);
+                }
+                Ok(r) if r.is_empty() => println!("Nothing to redo"),
+                Ok(r) => {
+                    let failed = r.iter().any(|(result, _)| is_failure(result));
+                    let mut table = Table::new("{:<}  {:>}  {:<}");
+                    for (direction, (result, migration)) in ["Down", "Up"].iter().zip(r) {
+                        table.add_row(
+                            Row::new()
+                                .with_cell(direction)
+                                .with_cell(format!("{:?}", result))
+                                .with_cell(
+                                    migration
+                                        .date_time
+                                        .format(crate::migrations::FORMAT_STR)
+                                        .to_string(),
+                                ),
+                        );
+                    }
+                    print!("{}", table);
+                    if failed {
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
         Some("generate-migration") => {
             info!("generating migration");
             let sub_m = m
@@ -329,11 +581,11 @@ mitre --help
 
             let config = config::from_file(config_file).expect("cannot read config");
 
-            match runner_from_config(&config, &key.to_string()) {
-                Ok(runner) => {
+            match driver_from_config(&config, key) {
+                Ok(driver) => {
                     let timestamp = chrono::Local::now().format(crate::migrations::FORMAT_STR);
 
-                    let (up_template, down_template, extension) = runner.migration_template();
+                    let template = driver.migration_template();
                     let target_path = migrations_dir.join(
                         format!(
                             "{}_{}.{}",
@@ -343,9 +595,10 @@ mitre --help
                         )
                         .as_str(),
                     );
-                    let up_target_path = target_path.join(format!("up.{}", extension).as_str());
-
-                    let down_target_path = target_path.join(format!("down.{}", extension).as_str());
+                    let up_target_path =
+                        target_path.join(format!("up.{}", template.extension).as_str());
+                    let down_target_path =
+                        target_path.join(format!("down.{}", template.extension).as_str());
                     info!(
                         "Generating migration into {}",
                         target_path
@@ -353,27 +606,22 @@ mitre --help
                             .expect("could not transform target_path to string")
                     );
 
-                    match std::fs::create_dir(target_path) {
-                        Ok(_) => match std::fs::write(up_target_path, up_template) {
-                            Ok(_) => match std::fs::write(down_target_path, down_template) {
-                                Ok(_) => {
-                                    info!("Generation done")
-                                }
-                                Err(e) => {
-                                    panic!("Could not write file: {}", e)
-                                }
-                            },
-                            Err(e) => {
-                                panic!("Could not write file: {}", e)
-                            }
-                        },
-                        Err(e) => {
-                            panic!("Could create dir: {}", e)
+                    if let Err(e) = std::fs::create_dir(target_path) {
+                        panic!("Could create dir: {}", e)
+                    }
+                    if let Err(e) = std::fs::write(up_target_path, template.up) {
+                        panic!("Could not write file: {}", e)
+                    }
+                    // Drivers which can't unapply migrations have no down template
+                    if let Some(down) = template.down {
+                        if let Err(e) = std::fs::write(down_target_path, down) {
+                            panic!("Could not write file: {}", e)
                         }
                     }
+                    info!("Generation done")
                 }
                 Err(e) => {
-                    panic!("Error finding runner {}", e)
+                    panic!("Error finding driver {:?}", e)
                 }
             }
         }
@@ -389,11 +637,171 @@ fn migration_storage(c: &Configuration) -> impl MigrationStorage {
     migration_storage_from_config(c).expect("should be able to make migration storage")
 }
 
+fn tag_filter(sub_m: Option<&ArgMatches>) -> TagFilter {
+    match sub_m {
+        Some(sub_m) => TagFilter::from_str_tags(
+            sub_m.value_of("include_tags").unwrap_or_default(),
+            sub_m.value_of("exclude_tags").unwrap_or_default(),
+        ),
+        None => TagFilter::default(),
+    }
+}
+
+fn version_arg(sub_m: &ArgMatches, name: &str) -> Option<String> {
+    sub_m.value_of(name).map(|version| {
+        match chrono::NaiveDateTime::parse_from_str(version, migrations::FORMAT_STR) {
+            Ok(date_time) => date_time.format(migrations::FORMAT_STR).to_string(),
+            Err(e) => {
+                error!(
+                    "--{} {:?} is not a migration timestamp (YYYYMMDDHHMMSS): {}",
+                    name, version, e
+                );
+                std::process::exit(1);
+            }
+        }
+    })
+}
+
+fn target(sub_m: Option<&ArgMatches>) -> Target {
+    let sub_m = match sub_m {
+        Some(sub_m) => sub_m,
+        None => return Target::All,
+    };
+    if let Some(version) = version_arg(sub_m, "to") {
+        return Target::Version(version);
+    }
+    if let Some(version) = version_arg(sub_m, "only") {
+        return Target::Only(version);
+    }
+    match sub_m.value_of("steps").map(|n| n.parse::<usize>()) {
+        Some(Ok(n)) => Target::Steps(n),
+        Some(Err(e)) => {
+            error!("--steps must be a positive number: {}", e);
+            std::process::exit(1);
+        }
+        None => Target::All,
+    }
+}
+
+fn apply_options(sub_m: Option<&ArgMatches>) -> ApplyOptions {
+    ApplyOptions {
+        continue_on_error: sub_m
+            .map(|sub_m| sub_m.is_present("continue_on_error"))
+            .unwrap_or(false),
+        tags: tag_filter(sub_m),
+        target: target(sub_m),
+        strict: sub_m
+            .map(|sub_m| sub_m.is_present("strict"))
+            .unwrap_or(false),
+        lock_timeout: lock_timeout_arg(sub_m),
+    }
+}
+
+fn lock_timeout_arg(sub_m: Option<&ArgMatches>) -> Duration {
+    match sub_m.and_then(|sub_m| sub_m.value_of("lock_timeout")) {
+        Some(seconds) => match seconds.parse::<u64>() {
+            Ok(seconds) => Duration::from_secs(seconds),
+            Err(e) => {
+                error!("--lock-timeout must be a number of seconds: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => DEFAULT_LOCK_TIMEOUT,
+    }
+}
+
+fn exit_lock_timeout(timeout: Duration) -> ! {
+    error!(
+        "Could not acquire the migration lock within {}s, is another mitre running?",
+        timeout.as_secs()
+    );
+    std::process::exit(1);
+}
+
+fn is_failure(result: &MigrationResult) -> bool {
+    matches!(
+        result,
+        MigrationResult::Failure { .. } | MigrationResult::SkippedDueToEarlierError
+    )
+}
+
+// Prints the results of migrate or down, and exits non-zero when anything
+// failed, or was skipped, so that scripts notice a half-migrated state.
+fn print_results(results: impl Iterator<Item = MigrationResultTuple>) {
+    let mut failed = false;
+    let mut table = Table::new("{:>}  {:<}");
+    for (result, migration) in results {
+        failed = failed || is_failure(&result);
+        table.add_row(
+            Row::new().with_cell(format!("{:?}", result)).with_cell(
+                migration
+                    .date_time
+                    .format(crate::migrations::FORMAT_STR)
+                    .to_string(),
+            ),
+        );
+    }
+    print!("{}", table);
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn print_plan(plan: &[PlannedMigration]) {
+    if plan.is_empty() {
+        println!("Nothing to do");
+    }
+    for p in plan {
+        println!(
+            "{} {} ({})",
+            p.migration.version(),
+            p.migration.configuration_name,
+            p.driver
+        );
+        if p.steps.is_empty() {
+            println!("  irreversible, has no down step");
+        }
+        for step in &p.steps {
+            println!("  {:?} {}", step.direction, step.path.display());
+            for line in step.rendered.lines() {
+                println!("    {}", line);
+            }
+        }
+    }
+}
+
+fn plan_to_json(plan: &[PlannedMigration]) -> serde_json::Value {
+    serde_json::Value::Array(
+        plan.iter()
+            .map(|p| {
+                serde_json::json!({
+                    "version": p.migration.version(),
+                    "configuration_name": p.migration.configuration_name,
+                    "driver": p.driver,
+                    "flags": p.migration.flags.iter().map(|f| format!("{}", f)).collect::<Vec<String>>(),
+                    "steps": p.steps.iter().map(|step| serde_json::json!({
+                        "direction": format!("{:?}", step.direction),
+                        "path": step.path.display().to_string(),
+                        "source": step.rendered,
+                    })).collect::<Vec<serde_json::Value>>(),
+                })
+            })
+            .collect(),
+    )
+}
+
 fn apply<'a>(
     c: &'a Configuration,
     work_list: Option<Vec<&Direction>>,
+    options: ApplyOptions,
 ) -> Result<impl Iterator<Item = MigrationResultTuple> + 'a, mitre::Error> {
-    Engine::apply(c, migration_list(c), migration_storage(c), work_list)
+    Engine::apply(
+        c,
+        migration_list(c),
+        migration_storage(c),
+        work_list,
+        options,
+    )
 }
 
 #[cfg(test)]
@@ -28,6 +28,7 @@ my-mysql-db: &my-mysql-db
 
 my-elasticsearch:
   _driver: elasticsearch
+  index: my-index
   ip_or_hostname: es
   protocol: http
   logLevel: debug
@@ -126,7 +127,7 @@ pub enum ConfigProblem {
     /// Unsupported runner specified. See [crate::reserved] for supported runners.
     UnsupportedDriverSpecified,
     /// Certain databases (e.g ElasticSearch) have a concept of an index. This configuration option
-    /// is exposed as `{indexName}` within the templates of migrations targeting a runner where this
+    /// is exposed as `{{index}}` within the templates of migrations targeting a runner where this
     /// concept applies.
     NoIndexSpecified,
     /// Certain databases (e.g Redis) number their databases. This configuration option is exposed
@@ -146,6 +147,9 @@ pub enum ConfigProblem {
     /// It is good practice to specify passwords. From development environments in increasing confusing
     /// contemporary network topologies, through cloud-based and shared (e.g public) environments.
     NoPasswordSpecified,
+    /// File based databases (e.g SQLite) and the file storage need the path of the file,
+    /// Rails needs the path of the app.
+    NoPathSpecified,
 }
 
 /// Alias for a String when using a configuration name, e.g "mitre" is expected to refer to a
@@ -191,7 +195,10 @@ impl RunnerConfiguration {
     pub fn validate(&self) -> Result<(), Vec<ConfigProblem>> {
         let mut vec = Vec::new();
 
-        if reserved::runner_by_name(&self._driver).is_none() {
+        if reserved::runner_by_name(&self._driver).is_none()
+            && crate::registry::driver_meta(&self._driver).is_none()
+            && crate::plugin::runner_meta(&self._driver).is_none()
+        {
             vec.push(ConfigProblem::UnsupportedDriverSpecified);
         }
 
@@ -201,6 +208,19 @@ impl RunnerConfiguration {
             vec.push(ConfigProblem::NoDatabaseNumberSpecified)
         }
 
+        let driver = self._driver.to_lowercase();
+        if driver == reserved::ELASTICSEARCH.to_lowercase() && self.index.is_none() {
+            vec.push(ConfigProblem::NoIndexSpecified)
+        }
+
+        if (driver == reserved::SQLITE.to_lowercase()
+            || driver == reserved::FILE.to_lowercase()
+            || driver == reserved::RAILS.to_lowercase())
+            && self.path.is_none()
+        {
+            vec.push(ConfigProblem::NoPathSpecified)
+        }
+
         if !vec.is_empty() {
             Err(vec)
         } else {
@@ -260,6 +280,21 @@ fn dig_u16(yaml: &yaml_rust::Yaml, key: &str) -> Result<Option<u16>, ConfigError
     }
 }
 
+fn dig_u32(yaml: &yaml_rust::Yaml, key: &str) -> Result<Option<u32>, ConfigError> {
+    match dig_yaml_value(yaml, key) {
+        Ok(Yaml::Integer(value)) => {
+            if value > u32::MAX as i64 {
+                return Err(ConfigError::IntegerOutOfRange {
+                    value: value as u64,
+                    max: u32::MAX as u64,
+                });
+            }
+            Ok(Some(value as u32))
+        }
+        _ => Ok(None {}),
+    }
+}
+
 fn as_string(yaml: &yaml_rust::Yaml) -> String {
     match yaml {
         yaml_rust::Yaml::String(yaml) => yaml.to_owned(),
@@ -354,6 +389,15 @@ fn from_yaml(yaml_docs: Vec<yaml_rust::Yaml>) -> Result<Configuration, ConfigErr
                 },
                 username: dig_string(config_value, &String::from("username")),
                 password: dig_string(config_value, &String::from("password")),
+                path: dig_string(config_value, &String::from("path")),
+                protocol: dig_string(config_value, &String::from("protocol")),
+                interpreter: dig_string(config_value, &String::from("interpreter")),
+                virtualenv: dig_string(config_value, &String::from("virtualenv")),
+                timeout: match dig_u32(config_value, &String::from("timeout")) {
+                    Ok(res) => res,
+                    Err(e) => return Err(e),
+                },
+                rails_env: dig_string(config_value, &String::from("rails_env")),
             };
             hm.insert(as_string(k), c);
             Ok(())
@@ -478,8 +522,7 @@ mod tests {
             password: Some(String::from("example")),
             port: Some(3306),
             username: Some(String::from("root")),
-            database_number: None {},
-            index: None {},
+            ..Default::default()
         };
 
         assert_eq!(1, config.configured_drivers.keys().len());
@@ -561,13 +604,7 @@ mod tests {
 
         let c = RunnerConfiguration {
             _driver: String::from("foobarbaz"),
-            database: None {},
-            ip_or_hostname: None {},
-            password: None {},
-            port: None {},
-            username: None {},
-            database_number: None {},
-            index: None {},
+            ..Default::default()
         };
 
         assert_eq!(1, config.configured_drivers.keys().len());
@@ -1,4 +1,4 @@
-use super::{Error, Migration, MigrationStep, MySQL};
+use super::{Direction, Error, Migration, MigrationStep, RunnerMeta};
 
 #[cfg(test)]
 pub mod noop;
@@ -21,24 +21,80 @@ pub trait NamedDriver {
     where
         Self: Sized;
 }
+
+/// What `generate-migration` writes for a driver: the up (or change) step,
+/// and the down step if the driver can unapply migrations at all.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationTemplate {
+    pub extension: &'static str,
+    pub up: &'static str,
+    pub down: Option<&'static str>,
+}
+
 /// [`Driver`] is trait which can be implemented for one or more
 /// technologies for which we manage migrations/schema changes.
 ///
+/// A driver runs single steps, [`Driver::apply`] and [`Driver::unapply`]
+/// pick the step to run from a migration, and need only be overridden by
+/// drivers with special needs.
+///
 /// Drivers which cannot unapply migrations (e.g HTTP) must report
 /// [`NothingToDo`] because this is a *successful* operation, but
 /// also a no-op.
 pub trait Driver: NamedDriver {
-    // Apply will take a Migration and run any
-    fn apply(&mut self, _: &Migration) -> Result<DriverResult, Error>;
-    fn unapply(&mut self, _: &Migration) -> Result<DriverResult, Error>;
+    // Run a single step, whichever direction it is.
+    fn run(&mut self, _: &MigrationStep) -> Result<DriverResult, Error>;
+
+    // Apply runs the change, or up step of the migration, it is malformed
+    // with both, or neither.
+    fn apply(&mut self, m: &Migration) -> Result<DriverResult, Error> {
+        let direction = m.apply_direction()?;
+        self.run(&m.steps[&direction])
+    }
+
+    // Unapply runs the down step, if there is one.
+    fn unapply(&mut self, m: &Migration) -> Result<DriverResult, Error> {
+        match m.steps.get(&Direction::Down) {
+            Some(down) => self.run(down),
+            None => Ok(DriverResult::NothingToDo),
+        }
+    }
+
+    // Render the step's Mustache template exactly as it would be
+    // rendered before running it, without running anything.
+    fn render(&self, _: &MigrationStep) -> Result<String, Error>;
+
+    /// The starting point for new migrations, see [`MigrationTemplate`].
+    fn migration_template(&self) -> MigrationTemplate;
+
+    /// Provides metadata about this driver, for most that is the reserved
+    /// [`RunnerMeta`] of the runner it implements.
+    fn meta(&self) -> RunnerMeta<'static>;
 }
 
-// Subtrait for convenience about a driver that only runs a single step.
-// mostly used to keep implementations tidy and reusable.
-// The trait doesn't make sense alone, so only allow this to be a convenience
-// for existing drivers. (e.g noop::Driver doesn't need this)
-pub trait StepDriver: Driver {
-    fn run(&mut self, _: &MigrationStep) -> Result<DriverResult, Error>;
+// Compiles and renders a step's Mustache template with a driver's context,
+// `what` names the source in errors, e.g "queries" or "script".
+pub(crate) fn render_template(
+    source: &str,
+    ctx: &mustache::Data,
+    what: &str,
+) -> Result<String, Error> {
+    let template = match mustache::compile_str(source) {
+        Ok(template) => template,
+        Err(_e) => {
+            return Err(Error::QueryFailed {
+                reason: None {},
+                msg: format!("couldn't compile Mustache template of {}", what),
+            })
+        }
+    };
+    match template.render_data_to_string(ctx) {
+        Ok(q) => Ok(q),
+        Err(_e) => Err(Error::QueryFailed {
+            reason: None {},
+            msg: format!("couldn't render Mustache template of {}", what),
+        }),
+    }
 }
 
 // Given a config YAML such as:
@@ -60,7 +116,8 @@ pub trait StepDriver: Driver {
 //
 // Other errors can be returned when the value of _driver
 // is incorrect (e.g an unsupported runner due to conditional
-// features, or typos)
+// features, or typos), see crate::registry and crate::plugin
+// for which are supported.
 //
 // Some drivers may return an error if all configuration is
 // correct but the server is not responding, or credentials
@@ -82,18 +139,15 @@ pub fn from_config(
             configuration_name: config_name.to_string(),
         })?;
 
-    #[cfg(feature = "runner_mysql")]
-    log::trace!(
-        "comparing {} to {} and {}",
-        rc._driver.to_lowercase(),
-        crate::reserved::MYSQL.to_lowercase(),
-        crate::reserved::MARIA_DB.to_lowercase()
-    );
-    if rc._driver.to_lowercase() == crate::reserved::MYSQL.to_lowercase()
-        || rc._driver.to_lowercase() == crate::reserved::MARIA_DB.to_lowercase()
-    {
-        log::info!("matched, returning a MySQL driver");
-        return Ok(Box::new(MySQL::new(rc.clone())?));
+    if let Some(constructor) = crate::registry::driver(&rc._driver) {
+        return constructor(rc.clone());
+    }
+    // Anything else may be a plugin, an executable on the PATH
+    if let Some(executable) = crate::plugin::find(&rc._driver) {
+        return Ok(Box::new(crate::plugin::Plugin::new(
+            executable,
+            rc.clone(),
+        )?));
     }
     log::error!(
         "There seems to be no avaiable (not compiled, not enabled) runner for {} (runner: {})",
@@ -242,6 +296,23 @@ mod test {
     test_driver!(noop, { noop::Driver {} });
     test_driver!(succeed_or_fail, { succeed_or_fail::Driver {} });
     test_driver!(mysql, { succeed_or_fail::Driver {} });
+    #[cfg(feature = "runner_redis")]
+    test_driver!(redis, {
+        crate::Redis::new(crate::RunnerConfiguration {
+            _driver: String::from("redis"),
+            database_number: Some(1),
+            ip_or_hostname: Some(String::from("127.0.0.1")),
+            ..Default::default()
+        })
+        .unwrap()
+    });
+    #[cfg(feature = "runner_postgres")]
+    test_driver!(postgres, {
+        crate::PostgreSql::new(
+            crate::migration_storage::tests::test_postgresql_storage_configuration(),
+        )
+        .unwrap()
+    });
 
     // test that apply tries "up", and falls-back to "change" on apply
 
@@ -1,4 +1,6 @@
-use crate::{Direction, DriverResult, Error, Migration};
+use crate::{
+    Direction, DriverResult, Error, Migration, MigrationStep, MigrationTemplate, RunnerMeta,
+};
 
 pub struct Driver {}
 
@@ -9,6 +11,9 @@ impl crate::NamedDriver for Driver {
 }
 
 impl crate::Driver for Driver {
+    fn run(&mut self, _: &MigrationStep) -> Result<DriverResult, Error> {
+        Ok(DriverResult::NothingToDo)
+    }
     fn apply(&mut self, m: &Migration) -> Result<DriverResult, Error> {
         match (m.steps.get(&Direction::Up), m.steps.get(&Direction::Change)) {
             (Some(_), Some(_)) => Err(Error::MalformedMigration),
@@ -18,4 +23,21 @@ impl crate::Driver for Driver {
     fn unapply(&mut self, _: &Migration) -> Result<DriverResult, Error> {
         Ok(DriverResult::NothingToDo)
     }
+    fn render(&self, ms: &MigrationStep) -> Result<String, Error> {
+        Ok(ms.source.clone())
+    }
+    fn migration_template(&self) -> MigrationTemplate {
+        MigrationTemplate {
+            extension: "noop",
+            up: "",
+            down: None {},
+        }
+    }
+    fn meta(&self) -> RunnerMeta<'static> {
+        RunnerMeta {
+            name: "noop",
+            desc: "Does nothing, for tests",
+            exts: vec![],
+        }
+    }
 }
@@ -1,4 +1,6 @@
-use crate::{Direction, DriverResult, Error, Migration, MigrationStep};
+use crate::{
+    Direction, DriverResult, Error, Migration, MigrationStep, MigrationTemplate, RunnerMeta,
+};
 
 pub struct Driver {}
 
@@ -9,30 +11,48 @@ impl crate::NamedDriver for Driver {
 }
 
 impl crate::Driver for Driver {
-    // If the migration step source is SUCCEED it returns Success
-    // else it returns
-    fn apply(&mut self, m: &Migration) -> Result<DriverResult, Error> {
-        match (m.steps.get(&Direction::Up), m.steps.get(&Direction::Change)) {
-            (Some(_), Some(_)) => Err(Error::MalformedMigration),
-            _ => Ok(DriverResult::NothingToDo),
-        }?;
-        Ok(DriverResult::NothingToDo)
-    }
-    fn unapply(&mut self, _: &Migration) -> Result<DriverResult, Error> {
-        Ok(DriverResult::NothingToDo)
-    }
-}
-
-impl crate::StepDriver for Driver {
     fn run(&mut self, ms: &MigrationStep) -> Result<DriverResult, Error> {
         match ms.source.as_str() {
             "NOTHING_TO_DO" => Ok(DriverResult::NothingToDo),
             "SUCCESS" => Ok(DriverResult::Success),
             "MIGRATION_RUNNER_MISMATCH" => Ok(DriverResult::MigrationRunnerMismatch),
+            "FAILURE" => Err(Error::QueryFailed {
+                reason: None {},
+                msg: String::from("succeed or fail driver was asked to fail"),
+            }),
             _ => panic!(
                 "succeed or fail driver can't handle the source {}",
                 ms.source
             ),
         }
     }
+    // Runs the up or change step, see run() for which sources succeed
+    // and which fail.
+    fn apply(&mut self, m: &Migration) -> Result<DriverResult, Error> {
+        match (m.steps.get(&Direction::Up), m.steps.get(&Direction::Change)) {
+            (Some(_), Some(_)) => Err(Error::MalformedMigration),
+            (Some(step), None) | (None, Some(step)) => self.run(step),
+            (None, None) => Ok(DriverResult::NothingToDo),
+        }
+    }
+    fn unapply(&mut self, _: &Migration) -> Result<DriverResult, Error> {
+        Ok(DriverResult::NothingToDo)
+    }
+    fn render(&self, ms: &MigrationStep) -> Result<String, Error> {
+        Ok(ms.source.clone())
+    }
+    fn migration_template(&self) -> MigrationTemplate {
+        MigrationTemplate {
+            extension: "succeed_or_fail",
+            up: "SUCCESS",
+            down: None {},
+        }
+    }
+    fn meta(&self) -> RunnerMeta<'static> {
+        RunnerMeta {
+            name: "succeed_or_fail",
+            desc: "Succeeds, or fails, as the migration says, for tests",
+            exts: vec![],
+        }
+    }
 }
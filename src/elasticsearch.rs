@@ -0,0 +1,340 @@
+use super::{
+    driver, migrations::MigrationStep, Driver, DriverResult, Error, MigrationTemplate, NamedDriver,
+    RunnerConfiguration, RunnerMeta,
+};
+use indoc::indoc;
+use log::{debug, info, trace};
+
+// TCP ports are unsigned 16 bit ints
+// https://tools.ietf.org/html/rfc793#section-3.1
+const ELASTICSEARCH_DEFAULT_PORT: u16 = 9200;
+
+/// Runs `.es` migrations, a file of one or more requests, in the style of
+/// Kibana's console:
+///
+/// ```text
+/// PUT /{{index}}
+/// {
+///   "mappings": { "properties": { "name": { "type": "keyword" } } }
+/// }
+///
+/// POST /{{index}}/_doc
+/// { "name": "example" }
+/// ```
+///
+/// Each request starts with a line holding the method and the path,
+/// everything up to the next such line is the (JSON) body. Lines starting
+/// with # are comments. The configured `index` is available as `{{index}}`.
+pub struct Elasticsearch {
+    client: reqwest::blocking::Client,
+    config: RunnerConfiguration,
+}
+
+#[derive(Debug, PartialEq)]
+struct Request {
+    // The (1-based) line the request starts on, for error messages
+    line: usize,
+    method: String,
+    path: String,
+    body: String,
+}
+
+const METHODS: [&str; 6] = ["GET", "PUT", "POST", "DELETE", "HEAD", "PATCH"];
+
+// The method and path, if the line starts a request
+fn request_line(line: &str) -> Option<(String, String)> {
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(path), None) if METHODS.contains(&method.to_uppercase().as_str()) => {
+            Some((method.to_uppercase(), String::from(path)))
+        }
+        _ => None {},
+    }
+}
+
+fn parse_requests(source: &str) -> Result<Vec<Request>, (usize, String)> {
+    let mut requests: Vec<Request> = vec![];
+    for (n, line) in source.lines().enumerate() {
+        let n = n + 1;
+        if line.trim().starts_with('#') {
+            continue;
+        }
+        match (request_line(line), requests.last_mut()) {
+            (Some((method, path)), _) => requests.push(Request {
+                line: n,
+                method,
+                path,
+                body: String::new(),
+            }),
+            (None, Some(request)) => {
+                request.body.push_str(line);
+                request.body.push('\n');
+            }
+            (None, None) if line.trim().is_empty() => {}
+            (None, None) => return Err((n, format!("expected a method and path, got {:?}", line))),
+        }
+    }
+    if requests.is_empty() {
+        return Err((1, String::from("no requests found")));
+    }
+    Ok(requests)
+}
+
+// Elasticsearch answers 200 to some requests which did not do what was
+// asked, e.g. index creation which wasn't acknowledged by the cluster in
+// time, or bulk requests where some of the items failed.
+fn reported_failure(body: &str) -> Option<&'static str> {
+    let v: serde_json::Value = match serde_json::from_str(body) {
+        Ok(v) => v,
+        Err(_) => return None {},
+    };
+    if v.get("acknowledged") == Some(&serde_json::Value::Bool(false)) {
+        return Some("not acknowledged");
+    }
+    if v.get("errors") == Some(&serde_json::Value::Bool(true)) {
+        return Some("errors reported");
+    }
+    None {}
+}
+
+impl Elasticsearch {
+    pub fn new(config: RunnerConfiguration) -> Result<Self, Error> {
+        info!("using elasticsearch driver for {:?}", config.ip_or_hostname);
+        match reqwest::blocking::Client::builder().build() {
+            Ok(client) => Ok(Elasticsearch { client, config }),
+            Err(e) => Err(Error::ConnectionError {
+                msg: format!("Creating Elasticsearch client: {}", e),
+            }),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}://{}:{}/{}",
+            self.config.protocol.as_deref().unwrap_or("http"),
+            self.config.ip_or_hostname.as_deref().unwrap_or("127.0.0.1"),
+            self.config.port.unwrap_or(ELASTICSEARCH_DEFAULT_PORT),
+            path.trim_start_matches('/')
+        )
+    }
+
+    fn template_ctx(&self) -> mustache::Data {
+        mustache::MapBuilder::new()
+            .insert_str("index", self.config.index.clone().unwrap_or_default())
+            .build()
+    }
+
+    fn render_requests(&self, query: &str) -> Result<String, Error> {
+        driver::render_template(query, &self.template_ctx(), "requests")
+    }
+}
+
+impl Driver for Elasticsearch {
+    // As with Redis nothing is rolled back, the requests before a failing
+    // one stay applied, so the error names the line of the failing request.
+    fn run(&mut self, ms: &MigrationStep) -> Result<DriverResult, Error> {
+        let failed = |line: usize, msg: String| Error::QueryFailed {
+            reason: None {},
+            msg: format!("{}:{}: {}", ms.path.display(), line, msg),
+        };
+        let requests =
+            parse_requests(&self.render_requests(&ms.source)?).map_err(|(n, e)| failed(n, e))?;
+        for request in requests {
+            let url = self.url(&request.path);
+            trace!("{} {} from line {}", request.method, url, request.line);
+            let method = reqwest::Method::from_bytes(request.method.as_bytes())
+                .expect("only known methods start a request");
+            let mut builder = self.client.request(method, &url);
+            if let Some(username) = &self.config.username {
+                builder = builder.basic_auth(username, self.config.password.as_ref());
+            }
+            if !request.body.trim().is_empty() {
+                // The bulk APIs take newline delimited JSON, which must end
+                // with a newline, as every body here does.
+                let content_type = match request.path.contains("_bulk") {
+                    true => "application/x-ndjson",
+                    false => "application/json",
+                };
+                builder = builder
+                    .header(reqwest::header::CONTENT_TYPE, content_type)
+                    .body(request.body.clone());
+            }
+            let response = builder.send().map_err(|e| {
+                failed(
+                    request.line,
+                    format!("{} {} failed: {}", request.method, url, e),
+                )
+            })?;
+            let status = response.status();
+            let body = response.text().unwrap_or_default();
+            if !status.is_success() {
+                return Err(failed(
+                    request.line,
+                    format!("{} {} returned {}: {}", request.method, url, status, body),
+                ));
+            }
+            if let Some(failure) = reported_failure(&body) {
+                return Err(failed(
+                    request.line,
+                    format!("{} {} {}: {}", request.method, url, failure, body),
+                ));
+            }
+            debug!("{} {} returned {}: {}", request.method, url, status, body);
+        }
+        Ok(DriverResult::Success)
+    }
+
+    fn render(&self, ms: &MigrationStep) -> Result<String, Error> {
+        self.render_requests(&ms.source)
+    }
+
+    fn migration_template(&self) -> MigrationTemplate {
+        MigrationTemplate {
+            extension: "es",
+            up: indoc!(
+                r#"
+                PUT /{{index}}
+                {
+                  "mappings": { "properties": { "your_field": { "type": "keyword" } } }
+                }
+                "#
+            ),
+            down: Some(indoc!(
+                "
+                DELETE /{{index}}
+                "
+            )),
+        }
+    }
+
+    fn meta(&self) -> RunnerMeta<'static> {
+        crate::reserved::runner_by_name(crate::reserved::ELASTICSEARCH)
+            .expect("reserved word not found")
+    }
+}
+
+impl NamedDriver for Elasticsearch {
+    fn name() -> &'static str {
+        "elasticsearch"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+    use std::sync::mpsc::{channel, Receiver};
+
+    fn config(port: u16) -> RunnerConfiguration {
+        RunnerConfiguration {
+            _driver: String::from("elasticsearch"),
+            index: Some(String::from("products")),
+            ip_or_hostname: Some(String::from("127.0.0.1")),
+            port: Some(port),
+            ..Default::default()
+        }
+    }
+
+    fn step(source: &str) -> MigrationStep {
+        MigrationStep {
+            path: PathBuf::from("/foo/20210503213400_products.search.es"),
+            source: String::from(source),
+        }
+    }
+
+    // A stub Elasticsearch, answering one connection per JSON body given,
+    // with 200 OK. The request lines it was sent come back on the channel.
+    fn stub_server(bodies: Vec<&'static str>) -> (u16, Receiver<String>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = channel();
+        std::thread::spawn(move || {
+            for body in bodies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 4096];
+                let n = stream.read(&mut buf).unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]).into_owned();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+                let _ = tx.send(String::from(request.lines().next().unwrap_or_default()));
+            }
+        });
+        (port, rx)
+    }
+
+    #[test]
+    fn parses_requests_per_block() {
+        let source = "# create the index\nPUT /products\n{\n  \"settings\": {}\n}\n\npost /products/_doc\n{\"a\": 1}\n\nDELETE /old\n";
+        assert_eq!(
+            parse_requests(source),
+            Ok(vec![
+                Request {
+                    line: 2,
+                    method: String::from("PUT"),
+                    path: String::from("/products"),
+                    body: String::from("{\n  \"settings\": {}\n}\n\n"),
+                },
+                Request {
+                    line: 7,
+                    method: String::from("POST"),
+                    path: String::from("/products/_doc"),
+                    body: String::from("{\"a\": 1}\n\n"),
+                },
+                Request {
+                    line: 10,
+                    method: String::from("DELETE"),
+                    path: String::from("/old"),
+                    body: String::new(),
+                },
+            ])
+        );
+        assert_eq!(
+            parse_requests("{}\nPUT /products").err().map(|(n, _)| n),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn runs_every_request_with_the_index_templated() -> Result<(), String> {
+        let (port, request_lines) = stub_server(vec![
+            r#"{"acknowledged": true}"#,
+            r#"{"result": "created"}"#,
+        ]);
+        let mut es = Elasticsearch::new(config(port)).map_err(|e| format!("{:?}", e))?;
+        es.run(&step(
+            "PUT /{{index}}\n{}\n\nPOST /{{index}}/_doc\n{\"a\": 1}",
+        ))
+        .map_err(|e| format!("{:?}", e))?;
+        assert_eq!(
+            request_lines.iter().collect::<Vec<String>>(),
+            vec!["PUT /products HTTP/1.1", "POST /products/_doc HTTP/1.1"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn detects_unacknowledged_and_bulk_errors() -> Result<(), String> {
+        for (body, expected) in &[
+            (r#"{"acknowledged": false}"#, "not acknowledged"),
+            (
+                r#"{"took": 3, "errors": true, "items": []}"#,
+                "errors reported",
+            ),
+        ] {
+            let (port, _) = stub_server(vec![body]);
+            let mut es = Elasticsearch::new(config(port)).map_err(|e| format!("{:?}", e))?;
+            match es.run(&step("# comment\nPOST /_bulk\n{}")) {
+                Err(Error::QueryFailed { msg, .. })
+                    if msg.contains(":2: ") && msg.contains(expected) => {}
+                Err(e) => return Err(format!("expected {:?}, got {:?}", expected, e)),
+                Ok(_) => return Err(format!("expected {:?}, but it succeeded", expected)),
+            }
+        }
+        Ok(())
+    }
+}
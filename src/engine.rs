@@ -1,10 +1,161 @@
 use crate::Direction;
 
+use super::runner::Error as RunnerError;
 use super::{
-    driver_from_config, Error, Migration, MigrationList, MigrationResult, MigrationResultTuple,
-    MigrationState, MigrationStateTuple, MigrationStorage,
+    driver_from_config, Error, HistoryEntry, Migration, MigrationList, MigrationResult,
+    MigrationResultTuple, MigrationState, MigrationStateTuple, MigrationStorage,
 };
+use chrono::Utc;
 use itertools::Itertools;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Tags (flags) which are not run unless they are explicitly included.
+pub const DEFAULT_EXCLUDED_TAGS: [&str; 3] = ["data", "long", "risky"];
+
+/// Decides by their tags (flags) which pending migrations are run, pending
+/// migrations which are not run are reported as [`MigrationState::FilteredOut`].
+/// A tag which is both included and excluded is included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Default for TagFilter {
+    fn default() -> Self {
+        TagFilter {
+            include: vec![],
+            exclude: DEFAULT_EXCLUDED_TAGS
+                .iter()
+                .map(|tag| tag.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl TagFilter {
+    /// Takes comma separated lists of tags (e.g `data,long`) to include
+    /// and exclude on top of the defaults.
+    pub fn from_str_tags(include: &str, exclude: &str) -> Self {
+        let split = |s: &str| {
+            s.split(',')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect::<Vec<String>>()
+        };
+        let mut filter = TagFilter::default();
+        filter.include.extend(split(include));
+        filter.exclude.extend(split(exclude));
+        filter
+    }
+
+    pub fn allows(&self, m: &Migration) -> bool {
+        !m.flags.iter().any(|f| {
+            self.exclude.iter().any(|tag| tag == f.name)
+                && !self.include.iter().any(|tag| tag == f.name)
+        })
+    }
+}
+
+/// Narrows the work list of [`Engine::apply`]. Versions are compared in the
+/// format of [`Migration::version`], i.e `YYYYMMDDHHMMSS`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// Everything pending, or when rolling back, everything applied.
+    All,
+    /// Migrate up to and including the version, or roll back everything
+    /// newer than the version, leaving the version itself applied.
+    Version(String),
+    /// Only the first N migrations of the work list, (i.e the N oldest
+    /// pending, or the N newest applied when rolling back.)
+    Steps(usize),
+    /// Exactly one migration, which must be in the work list.
+    Only(String),
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Target::All
+    }
+}
+
+/// How long [`Engine::apply`] waits, by default, for a concurrent run to
+/// release the migration storage lock.
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Options which change how [`Engine::apply`] works through the work list.
+#[derive(Debug, Clone)]
+pub struct ApplyOptions {
+    /// By default the first failure stops the run and every remaining
+    /// migration is reported as skipped. Only enable this when the
+    /// configurations involved are independent of one another.
+    pub continue_on_error: bool,
+
+    /// Which pending migrations to run, by tag.
+    pub tags: TagFilter,
+
+    /// How far to migrate, or roll back.
+    pub target: Target,
+
+    /// Refuse to migrate at all while any applied migration has been
+    /// modified on disk since (see [`MigrationState::Modified`]).
+    pub strict: bool,
+
+    /// How long to wait for the migration storage lock, which is held for
+    /// the whole run so that concurrent runs cannot apply a migration twice.
+    pub lock_timeout: Duration,
+}
+
+impl Default for ApplyOptions {
+    fn default() -> Self {
+        ApplyOptions {
+            continue_on_error: false,
+            tags: TagFilter::default(),
+            target: Target::default(),
+            strict: false,
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+        }
+    }
+}
+
+// Holds the migration storage lock for as long as it lives, the lazily
+// evaluated results of Engine::apply own one, so the lock is released
+// however (and whenever) the caller stops consuming them.
+struct LockedStorage<S: MigrationStorage>(S);
+
+impl<S: MigrationStorage> LockedStorage<S> {
+    fn lock(mut storage: S, timeout: Duration) -> Result<Self, Error> {
+        storage.lock(timeout)?;
+        Ok(LockedStorage(storage))
+    }
+}
+
+impl<S: MigrationStorage> Drop for LockedStorage<S> {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.unlock() {
+            log::error!("Could not release the migration storage lock {:?}", e);
+        }
+    }
+}
+
+/// A step of a [`PlannedMigration`] with its template rendered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedStep {
+    pub direction: Direction,
+    pub path: PathBuf,
+    pub rendered: String,
+}
+
+/// A migration as [`Engine::plan`] expects it to be run. Rolling back a
+/// change-only (irreversible) migration is planned with no steps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedMigration {
+    pub migration: Migration,
+    pub driver: String,
+    pub steps: Vec<PlannedStep>,
+}
 
 pub struct Engine {}
 
@@ -12,6 +163,7 @@ impl Engine {
     pub fn diff(
         mut src: impl MigrationList,
         mut dest: impl MigrationStorage,
+        filter: &TagFilter,
     ) -> Result<impl Iterator<Item = MigrationStateTuple>, Error> {
         let uniq_fn = |m: &Migration| m.date_time;
         let tuple_uniq_fn = |m: &MigrationStateTuple| m.1.date_time;
@@ -23,14 +175,28 @@ impl Engine {
         let src_migrations = src.all()?.sorted_by(cmp_fn).unique_by(uniq_fn);
         let dest_migrations = dest.all()?.sorted_by(cmp_fn).unique_by(uniq_fn);
 
-        // Applied migrations appear in both sets
+        // Applied migrations appear in both sets, those whose source on disk
+        // no longer matches the source stored when they were applied have
+        // been modified since
+        let stored = dest_migrations
+            .clone()
+            .map(|m| (m.date_time, m))
+            .collect::<HashMap<_, _>>();
         let applied =
-            iter_set::union_by(src_migrations.clone(), dest_migrations.clone(), mut_cmp_fn)
-                .map(|m| (MigrationState::Applied, m));
-        // Pending migrations appear only in known, but not applied
+            iter_set::union_by(src_migrations.clone(), dest_migrations.clone(), mut_cmp_fn).map(
+                move |m| match stored.get(&m.date_time) {
+                    Some(stored) if m.source_differs(stored) => (MigrationState::Modified, m),
+                    _ => (MigrationState::Applied, m),
+                },
+            );
+        // Pending migrations appear only in known, but not applied, unless
+        // their tags are excluded by the filter
         let pending =
             iter_set::difference_by(src_migrations.clone(), dest_migrations.clone(), mut_cmp_fn)
-                .map(|m| (MigrationState::Pending, m));
+                .map(|m| match filter.allows(&m) {
+                    true => (MigrationState::Pending, m),
+                    false => (MigrationState::FilteredOut, m),
+                });
         // Orphan migrations appear only in applied, but not in known
         let orphan =
             iter_set::difference_by(dest_migrations.clone(), src_migrations.clone(), mut_cmp_fn)
@@ -43,44 +209,320 @@ impl Engine {
             .unique_by(tuple_uniq_fn))
     }
 
+    /// Apply (or with a `Direction::Down` in the work filter, roll back)
+    /// migrations. Pending migrations are applied oldest first, rolling back
+    /// walks the applied migrations newest first, running the `Down` step
+    /// and removing the record from the migration storage.
+    ///
+    /// Unless [`ApplyOptions::continue_on_error`] is set, the first failure
+    /// (or when rolling back, the first irreversible migration) halts the
+    /// run, remaining migrations are reported as
+    /// [`MigrationResult::SkippedDueToEarlierError`] so that nothing runs
+    /// against a half-migrated schema.
+    ///
+    /// The migration storage is locked until the returned results have been
+    /// consumed (or dropped), see [`ApplyOptions::lock_timeout`].
     pub fn apply<'a>(
         config: &'a crate::config::Configuration,
         src: impl MigrationList + 'a,
         dest: impl MigrationStorage + 'a,
-        _work_filter: Option<Vec<&Direction>>,
+        work_filter: Option<Vec<&Direction>>,
+        options: ApplyOptions,
     ) -> Result<impl Iterator<Item = MigrationResultTuple> + 'a, Error> {
-        let work_list = Engine::diff(src, dest)?;
+        let rollback = Engine::is_rollback(work_filter);
+        // Taken before the diff, otherwise two runs could both see the same
+        // migration pending
+        let mut dest = LockedStorage::lock(dest, options.lock_timeout)?;
+        let work_list = Engine::work_list(src, &mut dest.0, rollback, &options)?;
         let c = config.clone();
-        Ok(work_list.map(move |(state, migration)| {
+        let mut failed = false;
+        Ok(work_list.into_iter().map(move |(_state, migration)| {
             log::debug!("checking migration {:?}", migration);
-            match state {
-                MigrationState::Pending => {
-                    match driver_from_config(&c, &migration.configuration_name) {
-                        Ok(mut driver) => match driver.apply(&migration) {
-                            Ok(_) => (MigrationResult::Success, migration),
-                            Err(e) => (
-                                MigrationResult::Failure {
-                                    reason: format!("{:?}", e),
-                                },
-                                migration,
-                            ),
-                        },
-                        Err(e) => {
-                            log::error!("Error getting runner from config {:?}", e);
-                            (
-                                MigrationResult::Failure {
-                                    reason: format!("{:?}", e),
-                                },
-                                migration,
-                            )
-                        }
-                    }
+            if failed && !options.continue_on_error {
+                log::info!("skipping {} due to an earlier error", migration.version());
+                return (MigrationResult::SkippedDueToEarlierError, migration);
+            }
+            let result = Engine::attempt(&c, &mut dest.0, migration, rollback);
+            // Rolling back past an irreversible migration would leave the
+            // older ones undone underneath one which is still applied
+            if matches!(
+                result.0,
+                MigrationResult::Failure { .. } | MigrationResult::IrreversibleMigration
+            ) {
+                failed = true;
+            }
+            result
+        }))
+    }
+
+    /// Dry-run of [`Engine::apply`], takes the same arguments and returns
+    /// the migrations which would be run, in order, with the steps which
+    /// would be run rendered by their driver. Nothing is run or recorded.
+    pub fn plan(
+        config: &crate::config::Configuration,
+        src: impl MigrationList,
+        mut dest: impl MigrationStorage,
+        work_filter: Option<Vec<&Direction>>,
+        options: &ApplyOptions,
+    ) -> Result<Vec<PlannedMigration>, Error> {
+        let rollback = Engine::is_rollback(work_filter);
+        Engine::work_list(src, &mut dest, rollback, options)?
+            .into_iter()
+            .map(|(_state, migration)| {
+                let driver = driver_from_config(config, &migration.configuration_name)?;
+                let direction = Engine::direction(&migration, rollback)?;
+                let steps = migration
+                    .steps
+                    .get(&direction)
+                    .map(|step| {
+                        Ok(PlannedStep {
+                            direction,
+                            path: step.path.clone(),
+                            rendered: driver.render(step)?,
+                        })
+                    })
+                    .into_iter()
+                    .collect::<Result<Vec<PlannedStep>, Error>>()?;
+                Ok(PlannedMigration {
+                    driver: config
+                        .get(&migration.configuration_name)
+                        .map(|rc| rc._driver.clone())
+                        .unwrap_or_default(),
+                    steps,
+                    migration,
+                })
+            })
+            .collect()
+    }
+
+    /// Roll back the newest applied migration, or the given one, and apply
+    /// it again, as one would while iterating on a migration locally. The
+    /// results of the `Down` and of the `Up` (or `Change`) are returned in
+    /// that order, the latter is missing if rolling back failed. Nothing
+    /// happens when no migration has been applied yet.
+    pub fn redo(
+        config: &crate::config::Configuration,
+        src: impl MigrationList,
+        dest: impl MigrationStorage,
+        version: Option<String>,
+        lock_timeout: Duration,
+    ) -> Result<Vec<MigrationResultTuple>, Error> {
+        let options = ApplyOptions {
+            target: match version {
+                Some(version) => Target::Only(version),
+                None => Target::Steps(1),
+            },
+            lock_timeout,
+            ..ApplyOptions::default()
+        };
+        let mut dest = LockedStorage::lock(dest, options.lock_timeout)?;
+        let migration = match Engine::work_list(src, &mut dest.0, true, &options)?.pop() {
+            Some((_state, migration)) => migration,
+            None => return Ok(vec![]),
+        };
+        // Refuse before rolling anything back, re-applying a change-only
+        // migration over itself would never be what anybody wanted.
+        if !migration.steps.contains_key(&Direction::Down) {
+            return Err(Error::IrreversibleMigration {
+                version: migration.version(),
+            });
+        }
+        let down = Engine::attempt(config, &mut dest.0, migration, true);
+        if down.0 != MigrationResult::Success {
+            return Ok(vec![down]);
+        }
+        let up = Engine::attempt(config, &mut dest.0, down.1.clone(), false);
+        Ok(vec![down, up])
+    }
+
+    // The direction of the step which applying, or rolling back, runs, as
+    // the drivers pick it. Rolling back a migration without a Down step
+    // runs nothing.
+    fn direction(migration: &Migration, rollback: bool) -> Result<Direction, Error> {
+        match rollback {
+            true => Ok(Direction::Down),
+            false => migration.apply_direction(),
+        }
+    }
+
+    fn is_rollback(work_filter: Option<Vec<&Direction>>) -> bool {
+        work_filter
+            .map(|directions| directions.contains(&&Direction::Down))
+            .unwrap_or(false)
+    }
+
+    // The migrations to apply, or roll back, in the order in which to do so.
+    fn work_list(
+        src: impl MigrationList,
+        dest: &mut impl MigrationStorage,
+        rollback: bool,
+        options: &ApplyOptions,
+    ) -> Result<Vec<MigrationStateTuple>, Error> {
+        let diff = Engine::diff(src, dest, &options.tags)?.collect::<Vec<MigrationStateTuple>>();
+        if options.strict && !rollback {
+            let versions = diff
+                .iter()
+                .filter(|(state, _)| *state == MigrationState::Modified)
+                .map(|(_, m)| m.version())
+                .collect::<Vec<String>>();
+            if !versions.is_empty() {
+                return Err(Error::ModifiedMigrations { versions });
+            }
+        }
+        let mut work_list = diff
+            .into_iter()
+            .filter(|(state, migration)| match rollback {
+                // Built-in migrations hold Mitre's own state, never roll those back
+                true => {
+                    (*state == MigrationState::Applied || *state == MigrationState::Modified)
+                        && !migration.built_in
                 }
-                _ => {
-                    todo!("boom")
+                false => *state == MigrationState::Pending,
+            })
+            .collect::<Vec<MigrationStateTuple>>();
+        if rollback {
+            work_list.reverse();
+        }
+        Ok(match &options.target {
+            Target::All => work_list,
+            Target::Version(version) => work_list
+                .into_iter()
+                .filter(|(_state, m)| match rollback {
+                    true => m.version() > *version,
+                    false => m.version() <= *version,
+                })
+                .collect(),
+            Target::Steps(n) => work_list.into_iter().take(*n).collect(),
+            Target::Only(version) => {
+                match work_list
+                    .into_iter()
+                    .find(|(_state, m)| m.version() == *version)
+                {
+                    Some(work) => vec![work],
+                    None => {
+                        return Err(Error::MigrationNotFound {
+                            version: version.clone(),
+                        })
+                    }
                 }
             }
-        }))
+        })
+    }
+
+    // Applies (or rolls back) the migration and appends the attempt, whatever
+    // its outcome, to the history. Not being able to write the history is
+    // logged, but doesn't change the outcome, the migration has run by then.
+    fn attempt(
+        c: &crate::config::Configuration,
+        dest: &mut impl MigrationStorage,
+        migration: Migration,
+        rollback: bool,
+    ) -> MigrationResultTuple {
+        // A malformed migration fails to apply, the attempt is recorded as an Up
+        let direction = Engine::direction(&migration, rollback).unwrap_or(Direction::Up);
+        let started_at = Utc::now();
+        let result = match rollback {
+            true => Engine::unapply_migration(c, dest, migration),
+            false => Engine::apply_migration(c, dest, migration),
+        };
+        if let Err(e) = dest.record(HistoryEntry::new(
+            &result.1, direction, started_at, &result.0,
+        )) {
+            log::error!(
+                "Could not record {} in the migration history {:?}",
+                result.1.version(),
+                e
+            );
+        }
+        result
+    }
+
+    // Successfully applied migrations are recorded straight away, so that a
+    // later failure in the same run doesn't leave them looking pending.
+    fn apply_migration(
+        c: &crate::config::Configuration,
+        dest: &mut impl MigrationStorage,
+        migration: Migration,
+    ) -> MigrationResultTuple {
+        match driver_from_config(c, &migration.configuration_name) {
+            Ok(mut driver) => match driver.apply(&migration) {
+                Ok(_) => match dest.add(migration.clone()) {
+                    Ok(_) => (MigrationResult::Success, migration),
+                    Err(e) => {
+                        log::error!(
+                            "Ran {} but could not record it {:?}",
+                            migration.version(),
+                            e
+                        );
+                        (
+                            MigrationResult::Failure {
+                                reason: RunnerError::RecordingMigrationResult {
+                                    cause: format!("{:?}", e),
+                                }
+                                .to_string(),
+                            },
+                            migration,
+                        )
+                    }
+                },
+                Err(e) => (
+                    MigrationResult::Failure {
+                        reason: format!("{:?}", e),
+                    },
+                    migration,
+                ),
+            },
+            Err(e) => {
+                log::error!("Error getting runner from config {:?}", e);
+                (
+                    MigrationResult::Failure {
+                        reason: format!("{:?}", e),
+                    },
+                    migration,
+                )
+            }
+        }
+    }
+
+    // Change-only migrations have no way back, they are reported as
+    // irreversible and left recorded as applied.
+    fn unapply_migration(
+        c: &crate::config::Configuration,
+        dest: &mut impl MigrationStorage,
+        migration: Migration,
+    ) -> MigrationResultTuple {
+        if !migration.steps.contains_key(&Direction::Down) {
+            return (MigrationResult::IrreversibleMigration, migration);
+        }
+        let mut driver = match driver_from_config(c, &migration.configuration_name) {
+            Ok(driver) => driver,
+            Err(e) => {
+                log::error!("Error getting runner from config {:?}", e);
+                return (
+                    MigrationResult::Failure {
+                        reason: format!("{:?}", e),
+                    },
+                    migration,
+                );
+            }
+        };
+        if let Err(e) = driver.unapply(&migration) {
+            return (
+                MigrationResult::Failure {
+                    reason: format!("{:?}", e),
+                },
+                migration,
+            );
+        }
+        match dest.remove(migration.clone()) {
+            Ok(_) => (MigrationResult::Success, migration),
+            Err(e) => (
+                MigrationResult::Failure {
+                    reason: format!("{:?}", e),
+                },
+                migration,
+            ),
+        }
     }
 }
 
@@ -148,6 +590,15 @@ mod tests {
         MySQL::new(c.get("mitre").unwrap().clone()).unwrap()
     }
 
+    #[cfg(feature = "runner_sqlite")]
+    fn sqlite_migration_storage() -> impl MigrationStorage {
+        crate::migration_storage::tests::test_sqlite_storage()
+    }
+
+    fn file_migration_storage() -> impl MigrationStorage {
+        crate::migration_storage::tests::test_file_storage()
+    }
+
     fn non_empty_migration_list() -> impl MigrationStorage {
         let mut imms = empty_migration_list();
         for migration in fixture().iter() {
@@ -161,7 +612,11 @@ mod tests {
 
     #[test]
     fn test_diff_lists_unknown_dest_migrations_as_pending() -> Result<(), String> {
-        match Engine::diff(non_empty_migration_list(), empty_migration_list()) {
+        match Engine::diff(
+            non_empty_migration_list(),
+            empty_migration_list(),
+            &TagFilter::default(),
+        ) {
             Ok(r) => {
                 let r_vec = r.collect::<Vec<MigrationStateTuple>>();
                 assert_eq!(r_vec.len(), fixture().len());
@@ -176,7 +631,11 @@ mod tests {
 
     #[test]
     fn test_diff_lists_unknown_src_migrations_as_orphaned() -> Result<(), String> {
-        match Engine::diff(empty_migration_list(), non_empty_migration_list()) {
+        match Engine::diff(
+            empty_migration_list(),
+            non_empty_migration_list(),
+            &TagFilter::default(),
+        ) {
             Ok(r) => {
                 let r_vec = r.collect::<Vec<MigrationStateTuple>>();
                 assert_eq!(r_vec.len(), fixture().len());
@@ -191,7 +650,11 @@ mod tests {
 
     #[test]
     fn test_diff_lists_all_known_in_src_dest_migrations_as_applied() -> Result<(), String> {
-        match Engine::diff(non_empty_migration_list(), non_empty_migration_list()) {
+        match Engine::diff(
+            non_empty_migration_list(),
+            non_empty_migration_list(),
+            &TagFilter::default(),
+        ) {
             Ok(r) => {
                 let r_vec = r.collect::<Vec<MigrationStateTuple>>();
                 assert_eq!(r_vec.len(), fixture().len());
@@ -207,6 +670,11 @@ mod tests {
     #[test_case(config(), empty_migration_list(), empty_migration_storage() ; "with an in-memory store")]
     #[cfg(feature = "runner_mysql")]
     #[test_case(config(), empty_migration_list(), mysql_migration_storage(config()) ; "with an mysql store")]
+    #[cfg_attr(
+        feature = "runner_sqlite",
+        test_case(config(), empty_migration_list(), sqlite_migration_storage() ; "with an sqlite store")
+    )]
+    #[test_case(config(), empty_migration_list(), file_migration_storage() ; "with a file store")]
     fn test_empty_stores_with_no_config_apply_uniformyl(
         config: Configuration,
         src: impl MigrationList,
@@ -215,7 +683,7 @@ mod tests {
         // integration fixtures may have external state, reset them noisily
         dest.reset().unwrap();
         // let c = config::load
-        match Engine::apply(&config, src, dest, None {}) {
+        match Engine::apply(&config, src, dest, None {}, ApplyOptions::default()) {
             Ok(r) => {
                 for result in r {
                     println!("{:?}", result)
@@ -226,6 +694,663 @@ mod tests {
         }
     }
 
+    fn noop_config() -> Configuration {
+        Configuration::load_from_str(indoc::indoc! {"
+          ---
+          anything:
+            _driver: noop
+        "})
+        .unwrap()
+    }
+
+    #[test]
+    fn test_apply_down_unapplies_and_removes_applied_migrations() -> Result<(), String> {
+        let mut dest = non_empty_migration_list();
+        let results = Engine::apply(
+            &noop_config(),
+            non_empty_migration_list(),
+            &mut dest,
+            Some(vec![&Direction::Down]),
+            ApplyOptions::default(),
+        )
+        .map_err(|e| format!("{:?}", e))?
+        .collect::<Vec<MigrationResultTuple>>();
+        assert_eq!(results.len(), fixture().len());
+        for (result, _migration) in results {
+            assert_eq!(MigrationResult::Success, result);
+        }
+        assert_eq!(0, dest.all().map_err(|e| format!("{:?}", e))?.count());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_down_rolls_back_newest_first() -> Result<(), String> {
+        let older = fixture().remove(0);
+        let mut newer = older.clone();
+        newer.date_time = older.date_time + chrono::Duration::seconds(1);
+        let src = InMemoryMigrations::from(vec![older.clone(), newer.clone()]);
+        let dest = InMemoryMigrations::from(vec![older.clone(), newer.clone()]);
+        let versions = Engine::apply(
+            &noop_config(),
+            src,
+            dest,
+            Some(vec![&Direction::Down]),
+            ApplyOptions::default(),
+        )
+        .map_err(|e| format!("{:?}", e))?
+        .map(|(_result, migration)| migration.version())
+        .collect::<Vec<String>>();
+        assert_eq!(vec![newer.version(), older.version()], versions);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_down_reports_change_only_migrations_as_irreversible() -> Result<(), String> {
+        let mut migration = fixture().remove(0);
+        let older = Migration {
+            date_time: migration.date_time - chrono::Duration::seconds(1),
+            ..migration.clone()
+        };
+        migration.steps.remove(&Direction::Down);
+        let up = migration.steps.remove(&Direction::Up).unwrap();
+        migration.steps.insert(Direction::Change, up);
+        let migrations = vec![older, migration];
+        let mut dest = InMemoryMigrations::from(migrations.clone());
+        let results = Engine::apply(
+            &noop_config(),
+            InMemoryMigrations::from(migrations.clone()),
+            &mut dest,
+            Some(vec![&Direction::Down]),
+            ApplyOptions::default(),
+        )
+        .map_err(|e| format!("{:?}", e))?
+        .collect::<Vec<MigrationResultTuple>>();
+        assert_eq!(2, results.len());
+        assert_eq!(MigrationResult::IrreversibleMigration, results[0].0);
+        // The older one is left applied, underneath the irreversible one
+        assert_eq!(MigrationResult::SkippedDueToEarlierError, results[1].0);
+        assert_eq!(migrations[0].version(), results[1].1.version());
+        assert_eq!(2, dest.all().map_err(|e| format!("{:?}", e))?.count());
+
+        let results = Engine::apply(
+            &noop_config(),
+            InMemoryMigrations::from(migrations.clone()),
+            &mut dest,
+            Some(vec![&Direction::Down]),
+            ApplyOptions {
+                continue_on_error: true,
+                ..ApplyOptions::default()
+            },
+        )
+        .map_err(|e| format!("{:?}", e))?
+        .collect::<Vec<MigrationResultTuple>>();
+        assert_eq!(MigrationResult::IrreversibleMigration, results[0].0);
+        assert_eq!(MigrationResult::Success, results[1].0);
+        assert_eq!(1, dest.all().map_err(|e| format!("{:?}", e))?.count());
+        Ok(())
+    }
+
+    #[test]
+    fn test_redo_rolls_back_and_reapplies_the_newest_migration() -> Result<(), String> {
+        let m = three_reversible_migrations();
+        let mut dest = InMemoryMigrations::from(m.clone());
+        let results = Engine::redo(
+            &noop_config(),
+            InMemoryMigrations::from(m.clone()),
+            &mut dest,
+            None {},
+            DEFAULT_LOCK_TIMEOUT,
+        )
+        .map_err(|e| format!("{:?}", e))?;
+        assert_eq!(2, results.len());
+        for (result, migration) in &results {
+            assert_eq!(MigrationResult::Success, *result);
+            assert_eq!(m[2].version(), migration.version());
+        }
+        assert_eq!(3, dest.all().map_err(|e| format!("{:?}", e))?.count());
+        Ok(())
+    }
+
+    #[test]
+    fn test_redo_a_given_migration() -> Result<(), String> {
+        let m = three_reversible_migrations();
+        let results = Engine::redo(
+            &noop_config(),
+            InMemoryMigrations::from(m.clone()),
+            InMemoryMigrations::from(m.clone()),
+            Some(m[0].version()),
+            DEFAULT_LOCK_TIMEOUT,
+        )
+        .map_err(|e| format!("{:?}", e))?;
+        assert_eq!(
+            vec![m[0].version(), m[0].version()],
+            results
+                .iter()
+                .map(|(_, m)| m.version())
+                .collect::<Vec<String>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_redo_refuses_irreversible_migrations() -> Result<(), String> {
+        let mut migration = fixture().remove(0);
+        migration.steps.remove(&Direction::Down);
+        let mut dest = InMemoryMigrations::from(vec![migration.clone()]);
+        match Engine::redo(
+            &noop_config(),
+            InMemoryMigrations::from(vec![migration.clone()]),
+            &mut dest,
+            None {},
+            DEFAULT_LOCK_TIMEOUT,
+        ) {
+            Err(Error::IrreversibleMigration { version }) => {
+                assert_eq!(migration.version(), version)
+            }
+            r => {
+                return Err(format!(
+                    "expected an irreversible migration error, got {:?}",
+                    r
+                ))
+            }
+        }
+        assert_eq!(1, dest.all().map_err(|e| format!("{:?}", e))?.count());
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_reports_applied_migrations_edited_since_as_modified() -> Result<(), String> {
+        let m = three_reversible_migrations();
+        let mut edited = m.clone();
+        edited[1]
+            .steps
+            .get_mut(&Direction::Up)
+            .unwrap()
+            .source
+            .push_str("-- edited after the fact");
+        let states = Engine::diff(
+            InMemoryMigrations::from(edited),
+            InMemoryMigrations::from(m),
+            &TagFilter::default(),
+        )
+        .map_err(|e| format!("{:?}", e))?
+        .map(|(state, _)| state)
+        .collect::<Vec<MigrationState>>();
+        assert_eq!(
+            vec![
+                MigrationState::Applied,
+                MigrationState::Modified,
+                MigrationState::Applied
+            ],
+            states
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_apply_refuses_to_run_while_migrations_are_modified() -> Result<(), String> {
+        let m = three_reversible_migrations();
+        let mut edited = m.clone();
+        edited[0]
+            .steps
+            .get_mut(&Direction::Up)
+            .unwrap()
+            .source
+            .push_str("-- edited after the fact");
+        let mut dest = InMemoryMigrations::from(vec![m[0].clone()]);
+        let options = ApplyOptions {
+            strict: true,
+            ..ApplyOptions::default()
+        };
+        match Engine::apply(
+            &noop_config(),
+            InMemoryMigrations::from(edited.clone()),
+            &mut dest,
+            None {},
+            options,
+        ) {
+            Err(Error::ModifiedMigrations { versions }) => {
+                assert_eq!(vec![m[0].version()], versions)
+            }
+            Err(e) => return Err(format!("expected modified migrations error, got {:?}", e)),
+            Ok(_) => return Err(String::from("expected strict apply to refuse")),
+        };
+        assert_eq!(1, dest.all().map_err(|e| format!("{:?}", e))?.count());
+        // without strict mode the pending migrations run regardless
+        assert_eq!(
+            2,
+            Engine::apply(
+                &noop_config(),
+                InMemoryMigrations::from(edited),
+                &mut dest,
+                None {},
+                ApplyOptions::default(),
+            )
+            .map_err(|e| format!("{:?}", e))?
+            .count()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_refuses_to_run_while_the_storage_is_locked() -> Result<(), String> {
+        let mut dest = empty_migration_storage();
+        dest.lock(Duration::from_millis(0))
+            .map_err(|e| format!("{:?}", e))?;
+        let options = ApplyOptions {
+            lock_timeout: Duration::from_millis(10),
+            ..ApplyOptions::default()
+        };
+        match Engine::apply(
+            &noop_config(),
+            non_empty_migration_list(),
+            &mut dest,
+            None {},
+            options,
+        ) {
+            Err(Error::LockTimeout { timeout }) => {
+                assert_eq!(Duration::from_millis(10), timeout)
+            }
+            Err(e) => return Err(format!("expected a lock timeout, got {:?}", e)),
+            Ok(_) => return Err(String::from("expected apply to wait for the lock")),
+        };
+        assert_eq!(0, dest.all().map_err(|e| format!("{:?}", e))?.count());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_releases_the_lock_once_the_results_are_consumed() -> Result<(), String> {
+        let mut dest = empty_migration_storage();
+        Engine::apply(
+            &noop_config(),
+            non_empty_migration_list(),
+            &mut dest,
+            None {},
+            ApplyOptions::default(),
+        )
+        .map_err(|e| format!("{:?}", e))?
+        .for_each(drop);
+        dest.lock(Duration::from_millis(0))
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    #[test]
+    fn test_apply_records_every_attempt_in_the_history() -> Result<(), String> {
+        let mut dest = empty_migration_storage();
+        Engine::apply(
+            &succeed_or_fail_config(),
+            second_of_three_fails(),
+            &mut dest,
+            None {},
+            ApplyOptions::default(),
+        )
+        .map_err(|e| format!("{:?}", e))?
+        .for_each(drop);
+        let history = dest.history().map_err(|e| format!("{:?}", e))?;
+        // the third migration was skipped, never attempted
+        assert_eq!(
+            vec!["Success", "Failure"],
+            history
+                .iter()
+                .map(|entry| entry.outcome.as_str())
+                .collect::<Vec<&str>>()
+        );
+        assert_eq!(Direction::Change, history[1].direction);
+        assert!(history[1].error.is_some());
+        assert!(history[1].finished_at >= history[1].started_at);
+        Ok(())
+    }
+
+    fn succeed_or_fail_config() -> Configuration {
+        Configuration::load_from_str(indoc::indoc! {"
+          ---
+          anything:
+            _driver: succeed_or_fail
+        "})
+        .unwrap()
+    }
+
+    // Three change migrations, one second apart, the second of which fails.
+    fn second_of_three_fails() -> InMemoryMigrations {
+        let base = fixture().remove(0);
+        InMemoryMigrations::from(
+            ["SUCCESS", "FAILURE", "SUCCESS"]
+                .iter()
+                .enumerate()
+                .map(|(i, source)| Migration {
+                    date_time: base.date_time + chrono::Duration::seconds(i as i64),
+                    steps: vec![(
+                        Direction::Change,
+                        MigrationStep {
+                            path: PathBuf::from("built/in/migration"),
+                            source: String::from(*source),
+                        },
+                    )]
+                    .into_iter()
+                    .collect(),
+                    ..base.clone()
+                })
+                .collect::<Vec<Migration>>(),
+        )
+    }
+
+    #[test]
+    fn test_apply_skips_remaining_migrations_after_a_failure() -> Result<(), String> {
+        let results = Engine::apply(
+            &succeed_or_fail_config(),
+            second_of_three_fails(),
+            empty_migration_storage(),
+            None {},
+            ApplyOptions::default(),
+        )
+        .map_err(|e| format!("{:?}", e))?
+        .map(|(result, _migration)| result)
+        .collect::<Vec<MigrationResult>>();
+        assert_eq!(MigrationResult::Success, results[0]);
+        assert!(matches!(results[1], MigrationResult::Failure { .. }));
+        assert_eq!(MigrationResult::SkippedDueToEarlierError, results[2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_continues_after_a_failure_when_asked_to() -> Result<(), String> {
+        let results = Engine::apply(
+            &succeed_or_fail_config(),
+            second_of_three_fails(),
+            empty_migration_storage(),
+            None {},
+            ApplyOptions {
+                continue_on_error: true,
+                ..ApplyOptions::default()
+            },
+        )
+        .map_err(|e| format!("{:?}", e))?
+        .map(|(result, _migration)| result)
+        .collect::<Vec<MigrationResult>>();
+        assert_eq!(MigrationResult::Success, results[0]);
+        assert!(matches!(results[1], MigrationResult::Failure { .. }));
+        assert_eq!(MigrationResult::Success, results[2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_records_successful_migrations() -> Result<(), String> {
+        let mut dest = empty_migration_storage();
+        let results = Engine::apply(
+            &noop_config(),
+            non_empty_migration_list(),
+            &mut dest,
+            None {},
+            ApplyOptions::default(),
+        )
+        .map_err(|e| format!("{:?}", e))?
+        .collect::<Vec<MigrationResultTuple>>();
+        assert_eq!(fixture().len(), results.len());
+        let stored = dest
+            .all()
+            .map_err(|e| format!("{:?}", e))?
+            .collect::<Vec<Migration>>();
+        assert_eq!(fixture(), stored);
+        Ok(())
+    }
+
+    // A storage which lists nothing, and refuses to store anything.
+    struct UnwritableMigrations {}
+
+    impl MigrationList for UnwritableMigrations {
+        fn all(&mut self) -> Result<Box<dyn Iterator<Item = Migration>>, Error> {
+            Ok(Box::new(vec![].into_iter()))
+        }
+    }
+
+    impl MigrationStorage for UnwritableMigrations {
+        fn reset(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+        fn add(&mut self, _: Migration) -> Result<(), Error> {
+            Err(Error::ConfigurationIncomplete)
+        }
+        fn remove(&mut self, _: Migration) -> Result<(), Error> {
+            Err(Error::ConfigurationIncomplete)
+        }
+        fn lock(&mut self, _: Duration) -> Result<(), Error> {
+            Ok(())
+        }
+        fn unlock(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+        fn record(&mut self, _: HistoryEntry) -> Result<(), Error> {
+            Err(Error::ConfigurationIncomplete)
+        }
+        fn history(&mut self) -> Result<Vec<HistoryEntry>, Error> {
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    fn test_apply_reports_failure_when_recording_the_result_fails() -> Result<(), String> {
+        let results = Engine::apply(
+            &noop_config(),
+            non_empty_migration_list(),
+            UnwritableMigrations {},
+            None {},
+            ApplyOptions::default(),
+        )
+        .map_err(|e| format!("{:?}", e))?
+        .collect::<Vec<MigrationResultTuple>>();
+        match &results[0].0 {
+            MigrationResult::Failure { reason } => {
+                assert!(reason.contains("RecordingMigrationResult"));
+                Ok(())
+            }
+            r => Err(format!("expected a failure, got {:?}", r)),
+        }
+    }
+
+    fn tagged_migration_list(tags: &str) -> InMemoryMigrations {
+        let mut migration = fixture().remove(0);
+        migration.flags = crate::reserved::flags_from_str_flags(tags);
+        InMemoryMigrations::from(vec![migration])
+    }
+
+    #[test]
+    fn test_diff_lists_pending_migrations_with_excluded_tags_as_filtered_out() -> Result<(), String>
+    {
+        for tags in DEFAULT_EXCLUDED_TAGS.iter() {
+            let states = Engine::diff(
+                tagged_migration_list(tags),
+                empty_migration_storage(),
+                &TagFilter::default(),
+            )
+            .map_err(|e| format!("{:?}", e))?
+            .map(|(state, _migration)| state)
+            .collect::<Vec<MigrationState>>();
+            assert_eq!(vec![MigrationState::FilteredOut], states, "tag {}", tags);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_lists_pending_migrations_with_included_tags_as_pending() -> Result<(), String> {
+        let states = Engine::diff(
+            tagged_migration_list("data,long"),
+            empty_migration_storage(),
+            &TagFilter::from_str_tags("data, long", ""),
+        )
+        .map_err(|e| format!("{:?}", e))?
+        .map(|(state, _migration)| state)
+        .collect::<Vec<MigrationState>>();
+        assert_eq!(vec![MigrationState::Pending], states);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_does_not_run_filtered_out_migrations() -> Result<(), String> {
+        let mut dest = empty_migration_storage();
+        let results = Engine::apply(
+            &noop_config(),
+            tagged_migration_list("risky"),
+            &mut dest,
+            None {},
+            ApplyOptions::default(),
+        )
+        .map_err(|e| format!("{:?}", e))?
+        .collect::<Vec<MigrationResultTuple>>();
+        assert!(results.is_empty());
+        assert_eq!(0, dest.all().map_err(|e| format!("{:?}", e))?.count());
+        Ok(())
+    }
+
+    // Three reversible migrations, one second apart, oldest first.
+    fn three_reversible_migrations() -> Vec<Migration> {
+        let base = fixture().remove(0);
+        (0..3)
+            .map(|i| Migration {
+                date_time: base.date_time + chrono::Duration::seconds(i),
+                ..base.clone()
+            })
+            .collect()
+    }
+
+    fn versions_after_apply(
+        src: Vec<Migration>,
+        dest: Vec<Migration>,
+        work_filter: Option<Vec<&Direction>>,
+        target: Target,
+    ) -> Result<Vec<String>, String> {
+        Ok(Engine::apply(
+            &noop_config(),
+            InMemoryMigrations::from(src),
+            InMemoryMigrations::from(dest),
+            work_filter,
+            ApplyOptions {
+                target,
+                ..ApplyOptions::default()
+            },
+        )
+        .map_err(|e| format!("{:?}", e))?
+        .map(|(_result, migration)| migration.version())
+        .collect())
+    }
+
+    #[test]
+    fn test_apply_with_a_version_target_migrates_up_to_and_including_it() -> Result<(), String> {
+        let m = three_reversible_migrations();
+        let versions =
+            versions_after_apply(m.clone(), vec![], None {}, Target::Version(m[1].version()))?;
+        assert_eq!(vec![m[0].version(), m[1].version()], versions);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_down_with_a_version_target_rolls_back_everything_newer() -> Result<(), String> {
+        let m = three_reversible_migrations();
+        let versions = versions_after_apply(
+            m.clone(),
+            m.clone(),
+            Some(vec![&Direction::Down]),
+            Target::Version(m[0].version()),
+        )?;
+        assert_eq!(vec![m[2].version(), m[1].version()], versions);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_down_with_a_steps_target_rolls_back_the_newest() -> Result<(), String> {
+        let m = three_reversible_migrations();
+        let versions = versions_after_apply(
+            m.clone(),
+            m.clone(),
+            Some(vec![&Direction::Down]),
+            Target::Steps(1),
+        )?;
+        assert_eq!(vec![m[2].version()], versions);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_with_an_only_target_runs_exactly_that_migration() -> Result<(), String> {
+        let m = three_reversible_migrations();
+        let versions =
+            versions_after_apply(m.clone(), vec![], None {}, Target::Only(m[1].version()))?;
+        assert_eq!(vec![m[1].version()], versions);
+        match versions_after_apply(m.clone(), m.clone(), None {}, Target::Only(m[1].version())) {
+            Err(e) => {
+                assert!(e.contains("MigrationNotFound"));
+                Ok(())
+            }
+            Ok(v) => Err(format!("expected an error, got {:?}", v)),
+        }
+    }
+
+    #[test]
+    fn test_plan_renders_the_steps_which_would_run_without_running_them() -> Result<(), String> {
+        let m = three_reversible_migrations();
+        let mut dest = empty_migration_storage();
+        let plan = Engine::plan(
+            &noop_config(),
+            InMemoryMigrations::from(m.clone()),
+            &mut dest,
+            None {},
+            &ApplyOptions::default(),
+        )
+        .map_err(|e| format!("{:?}", e))?;
+        assert_eq!(
+            m.iter().map(|m| m.version()).collect::<Vec<String>>(),
+            plan.iter()
+                .map(|p| p.migration.version())
+                .collect::<Vec<String>>()
+        );
+        for p in plan {
+            assert_eq!("noop", p.driver);
+            assert_eq!(1, p.steps.len());
+            assert_eq!(Direction::Up, p.steps[0].direction);
+            assert_eq!(
+                p.migration.steps[&Direction::Up].source,
+                p.steps[0].rendered
+            );
+        }
+        assert_eq!(0, dest.all().map_err(|e| format!("{:?}", e))?.count());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_reports_migrations_with_both_up_and_change_steps() -> Result<(), String> {
+        let mut m = three_reversible_migrations();
+        let up = m[0].steps[&Direction::Up].clone();
+        m[0].steps.insert(Direction::Change, up);
+        match Engine::plan(
+            &noop_config(),
+            InMemoryMigrations::from(m),
+            empty_migration_storage(),
+            None {},
+            &ApplyOptions::default(),
+        ) {
+            Err(Error::MalformedMigration) => Ok(()),
+            Err(e) => Err(format!("unexpected error {:?}", e)),
+            Ok(_) => Err(String::from("planned a malformed migration")),
+        }
+    }
+
+    #[test]
+    fn test_plan_down_plans_the_down_steps_newest_first() -> Result<(), String> {
+        let m = three_reversible_migrations();
+        let plan = Engine::plan(
+            &noop_config(),
+            InMemoryMigrations::from(m.clone()),
+            InMemoryMigrations::from(m.clone()),
+            Some(vec![&Direction::Down]),
+            &ApplyOptions::default(),
+        )
+        .map_err(|e| format!("{:?}", e))?;
+        assert_eq!(m[2].version(), plan[0].migration.version());
+        for p in plan {
+            assert_eq!(1, p.steps.len());
+            assert_eq!(Direction::Down, p.steps[0].direction);
+        }
+        Ok(())
+    }
+
     // test that given a MySQL + PostgreSQL + Redis driver, for all supported storages
     // we store the state and can migrate across the board. One each migration each
     // driver.
@@ -1,4 +1,4 @@
-use crate::{migration_list_from_disk, migration_storage_from_config, Engine};
+use crate::{migration_list_from_disk, migration_storage_from_config, Engine, TagFilter};
 
 use log::{error, trace, warn};
 use std::ffi::{CStr, CString};
@@ -228,58 +228,74 @@ unsafe extern "C" fn free_config_from_file(c: *mut Configuration) {
 // https://doc.rust-lang.org/nomicon/ffi.html#representing-opaque-structs
 #[no_mangle]
 unsafe extern "C" fn diff(c: *mut crate::config::Configuration) -> *mut MigrationStates {
+    diff_with_tag_filter(c, std::ptr::null(), std::ptr::null())
+}
+
+/// As `diff()` but with comma separated lists of tags to include and exclude
+/// on top of the default [`crate::TagFilter`], either may be a null pointer.
+/// Migrations which are excluded are reported in the `FilteredOut` state.
+#[no_mangle]
+unsafe extern "C" fn diff_with_tag_filter(
+    c: *mut crate::config::Configuration,
+    include_tags: *const c_char,
+    exclude_tags: *const c_char,
+) -> *mut MigrationStates {
     let rc = Box::from_raw(c);
     let migrations_from_disk = migration_list_from_disk(&rc);
+    let filter = TagFilter::from_str_tags(str_or_empty(include_tags), str_or_empty(exclude_tags));
 
     let migration_states = match migration_storage_from_config(&rc) {
-        Ok(migration_storage) => match Engine::diff(migrations_from_disk, migration_storage) {
-            Ok(diff_results) => {
-                let mut num_migration_states: usize = 0;
-                let mut migration_states: Vec<MigrationState> = vec![];
-                for (migration_state, migration) in diff_results {
-                    num_migration_states += 1;
-                    let mut num_steps: usize = 0;
-                    let mut steps: Vec<MigrationStep> = vec![];
-                    for (direction, step) in migration.steps {
-                        num_steps += 1;
-                        steps.push(MigrationStep {
-                            direction: CString::new(format!("{:?}", direction))
+        Ok(migration_storage) => {
+            match Engine::diff(migrations_from_disk, migration_storage, &filter) {
+                Ok(diff_results) => {
+                    let mut num_migration_states: usize = 0;
+                    let mut migration_states: Vec<MigrationState> = vec![];
+                    for (migration_state, migration) in diff_results {
+                        num_migration_states += 1;
+                        let mut num_steps: usize = 0;
+                        let mut steps: Vec<MigrationStep> = vec![];
+                        for (direction, step) in migration.steps {
+                            num_steps += 1;
+                            steps.push(MigrationStep {
+                                direction: CString::new(format!("{:?}", direction))
+                                    .unwrap_or_default()
+                                    .into_raw(),
+                                path: CString::new(step.path.to_str().unwrap_or_default())
+                                    .unwrap_or_default()
+                                    .into_raw(),
+                                source: CString::new(step.source).unwrap().into_raw(),
+                            })
+                        }
+                        migration_states.push(MigrationState {
+                            state: CString::new(format!("{:?}", migration_state))
                                 .unwrap_or_default()
                                 .into_raw(),
-                            path: CString::new(step.path.to_str().unwrap_or_default())
+                            migration: Box::into_raw(Box::new(Migration {
+                                date_time: CString::new(format!(
+                                    "{}",
+                                    migration.date_time.format(crate::migrations::FORMAT_STR)
+                                ))
                                 .unwrap_or_default()
                                 .into_raw(),
-                            source: CString::new(step.source).unwrap().into_raw(),
-                        })
+                                steps: Box::into_raw(steps.into_boxed_slice())
+                                    as *mut MigrationStep,
+                                built_in: 1,
+                                num_steps,
+                            })),
+                        });
+                    }
+                    MigrationStates {
+                        migration_state: Box::into_raw(migration_states.into_boxed_slice())
+                            as *mut MigrationState,
+                        num_migration_states,
                     }
-                    migration_states.push(MigrationState {
-                        state: CString::new(format!("{:?}", migration_state))
-                            .unwrap_or_default()
-                            .into_raw(),
-                        migration: Box::into_raw(Box::new(Migration {
-                            date_time: CString::new(format!(
-                                "{}",
-                                migration.date_time.format(crate::migrations::FORMAT_STR)
-                            ))
-                            .unwrap_or_default()
-                            .into_raw(),
-                            steps: Box::into_raw(steps.into_boxed_slice()) as *mut MigrationStep,
-                            built_in: 1,
-                            num_steps,
-                        })),
-                    });
                 }
-                MigrationStates {
-                    migration_state: Box::into_raw(migration_states.into_boxed_slice())
-                        as *mut MigrationState,
-                    num_migration_states,
+                Err(e) => {
+                    error!("could not run diff() on state store {:?}", e);
+                    return std::ptr::null_mut();
                 }
             }
-            Err(e) => {
-                error!("could not run diff() on state store {:?}", e);
-                return std::ptr::null_mut();
-            }
-        },
+        }
         Err(e) => {
             error!("could not make state store from config: {:?}", e);
             return std::ptr::null_mut();
@@ -289,6 +305,19 @@ unsafe extern "C" fn diff(c: *mut crate::config::Configuration) -> *mut Migratio
     Box::into_raw(Box::new(migration_states))
 }
 
+unsafe fn str_or_empty<'a>(p: *const c_char) -> &'a str {
+    if p.is_null() {
+        return "";
+    }
+    match CStr::from_ptr(p).to_str() {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("could not create string from pointer: {:?}", e);
+            ""
+        }
+    }
+}
+
 /// Free the results allocated by `diff()`.
 /// This function will not free the pointer associated with the configuration as the configuration
 /// is cloned into the state store implementation to avoid long-living references, anyway.
@@ -75,6 +75,10 @@ global.libmitre = ffi.Library("./target/debug/libmitre", {
   init_logger: ["void", [LogCallbacksPtr]],
   config_from_file: [ConfigurationPtr, [ref.types.CString]],
   diff: [MigrationStatesPtr, ["pointer"]],
+  diff_with_tag_filter: [
+    MigrationStatesPtr,
+    ["pointer", ref.types.CString, ref.types.CString],
+  ],
 });
 
 // https://github.com/search?q=ffi.Library&type=Code&l=JavaScript
@@ -148,12 +152,19 @@ global.mitre = {
     return result;
   },
 
-  diff: (config) => {
+  // includeTags and excludeTags are arrays of tags (e.g ["data", "long"]) which
+  // are added to the default filter, by default data, long and risky migrations
+  // are reported as FilteredOut rather than Pending.
+  diff: (config, { includeTags = [], excludeTags = [] } = {}) => {
     if (!config) {
       throw new Error("diff expects a config");
     }
     const { migration_states, num_migration_states } = libmitre
-      .diff(config._mitre_rust_config)
+      .diff_with_tag_filter(
+        config._mitre_rust_config,
+        includeTags.join(","),
+        excludeTags.join(",")
+      )
       .deref();
     const migrationStates = []; // lol const
     migration_states.length = num_migration_states;
@@ -0,0 +1,423 @@
+use chrono::prelude::*;
+use fs2::FileExt;
+use serde_json::{json, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::{
+    migrations::{Direction, MigrationStep},
+    Error, HistoryEntry, Migration, MigrationList, MigrationStorage, RunnerConfiguration,
+};
+use log::{debug, info, trace};
+
+use crate::migrations::FORMAT_STR;
+
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Keeps Mitre's state in a single YAML file (or JSON, when the path ends in
+/// `.json`) for projects with no database to hold it, for example those which
+/// only run HTTP migrations against Elasticsearch. The file may live on a
+/// shared volume, every write replaces it atomically and concurrent runs are
+/// serialized by an advisory lock on a file beside it.
+pub struct FileStorage {
+    path: PathBuf,
+    // The lock file, held open (and locked) for as long as the storage is
+    // locked
+    lock: Option<File>,
+}
+
+// The file as stored, each list holds one object per migration, or history
+// entry, the fields of which mirror the columns of the SQL storages.
+struct Document {
+    migrations: Vec<Value>,
+    history: Vec<Value>,
+}
+
+fn malformed(path: &Path, msg: String) -> Error {
+    Error::MalformedStorageFile {
+        path: path.to_path_buf(),
+        msg,
+    }
+}
+
+fn field<'a>(path: &Path, v: &'a Value, key: &str) -> Result<&'a Value, Error> {
+    v.get(key)
+        .ok_or_else(|| malformed(path, format!("missing {:?} in {}", key, v)))
+}
+
+fn string_field(path: &Path, v: &Value, key: &str) -> Result<String, Error> {
+    match field(path, v, key)? {
+        Value::String(s) => Ok(s.clone()),
+        // A version edited by hand may well have lost its quotes
+        Value::Number(n) => Ok(n.to_string()),
+        other => Err(malformed(
+            path,
+            format!("expected {:?} to be a string, got {}", key, other),
+        )),
+    }
+}
+
+fn date_time_field(path: &Path, v: &Value, key: &str) -> Result<DateTime<Utc>, Error> {
+    let s = string_field(path, v, key)?;
+    match DateTime::parse_from_rfc3339(&s) {
+        Ok(date_time) => Ok(date_time.with_timezone(&Utc)),
+        Err(e) => Err(malformed(path, format!("{:?} in {:?}: {}", s, key, e))),
+    }
+}
+
+// Versions are written as strings, but one edited by hand may be a number
+fn version_of(v: &Value) -> Option<String> {
+    match v.get("version") {
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(Value::Number(n)) => Some(n.to_string()),
+        _ => None {},
+    }
+}
+
+fn format_date_time(date_time: &DateTime<Utc>) -> String {
+    date_time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn direction_name(direction: &Direction) -> String {
+    format!("{:?}", direction).to_lowercase()
+}
+
+fn migration_to_value(m: &Migration) -> Value {
+    json!({
+        "version": m.version(),
+        "stored_at": format_date_time(&Utc::now()),
+        "flags": m.flags.iter().map(|f| f.name).collect::<Vec<&str>>(),
+        "configuration_name": m.configuration_name,
+        "built_in": m.built_in,
+        "steps": m.steps.iter().map(|(direction, s)| (
+            direction_name(direction),
+            json!({
+                "path": s.path.to_string_lossy(),
+                "source": s.source,
+            }),
+        )).collect::<serde_json::Map<String, Value>>(),
+    })
+}
+
+fn migration_from_value(path: &Path, v: &Value) -> Result<Migration, Error> {
+    let version = string_field(path, v, "version")?;
+    let date_time = match chrono::NaiveDateTime::parse_from_str(&version, FORMAT_STR) {
+        Ok(date_time) => date_time,
+        Err(e) => return Err(malformed(path, format!("version {:?}: {}", version, e))),
+    };
+    let flags = match field(path, v, "flags")?.as_array() {
+        Some(flags) => flags
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<&str>>()
+            .join(","),
+        None => {
+            return Err(malformed(
+                path,
+                format!("flags of {} is not a list", version),
+            ))
+        }
+    };
+    let steps = match field(path, v, "steps")?.as_object() {
+        Some(steps) => steps
+            .iter()
+            .map(|(direction, step)| {
+                Ok((
+                    Direction::from(direction.clone()),
+                    MigrationStep {
+                        path: PathBuf::from(string_field(path, step, "path")?),
+                        source: string_field(path, step, "source")?,
+                    },
+                ))
+            })
+            .collect::<Result<crate::MigrationSteps, Error>>()?,
+        None => {
+            return Err(malformed(
+                path,
+                format!("steps of {} is not a map", version),
+            ))
+        }
+    };
+    Ok(Migration {
+        date_time,
+        flags: crate::reserved::flags_from_str_flags(&flags),
+        configuration_name: string_field(path, v, "configuration_name")?,
+        built_in: field(path, v, "built_in")?.as_bool().unwrap_or(false),
+        steps,
+    })
+}
+
+fn history_entry_to_value(entry: &HistoryEntry) -> Value {
+    json!({
+        "version": entry.version,
+        "direction": direction_name(&entry.direction),
+        "started_at": format_date_time(&entry.started_at),
+        "finished_at": format_date_time(&entry.finished_at),
+        "outcome": entry.outcome,
+        "error": entry.error,
+        "hostname": entry.hostname,
+        "mitre_version": entry.mitre_version,
+    })
+}
+
+fn history_entry_from_value(path: &Path, v: &Value) -> Result<HistoryEntry, Error> {
+    Ok(HistoryEntry {
+        version: string_field(path, v, "version")?,
+        direction: Direction::from(string_field(path, v, "direction")?),
+        started_at: date_time_field(path, v, "started_at")?,
+        finished_at: date_time_field(path, v, "finished_at")?,
+        outcome: string_field(path, v, "outcome")?,
+        error: match v.get("error") {
+            None | Some(Value::Null) => None {},
+            Some(_) => Some(string_field(path, v, "error")?),
+        },
+        hostname: string_field(path, v, "hostname")?,
+        mitre_version: string_field(path, v, "mitre_version")?,
+    })
+}
+
+impl FileStorage {
+    pub fn new(config: RunnerConfiguration) -> Result<Self, Error> {
+        match config.path {
+            Some(path) => {
+                info!("using file migration storage {:?}", path);
+                Ok(FileStorage {
+                    path: PathBuf::from(path),
+                    lock: None {},
+                })
+            }
+            None => Err(Error::ConfigurationIncomplete),
+        }
+    }
+
+    fn is_json(&self) -> bool {
+        match self.path.extension() {
+            Some(ext) => ext == "json",
+            None => false,
+        }
+    }
+
+    // A path beside the storage, e.g. mitre.yml.lock for mitre.yml
+    fn sibling(&self, suffix: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".");
+        path.push(suffix);
+        PathBuf::from(path)
+    }
+
+    fn read(&self) -> Result<Document, Error> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!(
+                    "early return with empty storage, {:?} does not exist",
+                    self.path
+                );
+                return Ok(Document {
+                    migrations: vec![],
+                    history: vec![],
+                });
+            }
+            Err(e) => return Err(Error::Io(e)),
+        };
+        let v: Value = if self.is_json() {
+            serde_json::from_str(&contents).map_err(|e| malformed(&self.path, e.to_string()))?
+        } else {
+            serde_yaml::from_str(&contents).map_err(|e| malformed(&self.path, e.to_string()))?
+        };
+        let list = |key: &str| match v.get(key) {
+            None | Some(Value::Null) => Ok(vec![]),
+            Some(Value::Array(list)) => Ok(list.clone()),
+            Some(_) => Err(malformed(&self.path, format!("{:?} is not a list", key))),
+        };
+        Ok(Document {
+            migrations: list("migrations")?,
+            history: list("history")?,
+        })
+    }
+
+    // The document is written beside the storage and renamed over it, so
+    // that neither a concurrent reader nor a run which dies half way through
+    // writing can ever leave a partially written file behind.
+    fn write(&self, doc: Document) -> Result<(), Error> {
+        let v = json!({
+            "migrations": doc.migrations,
+            "history": doc.history,
+        });
+        let contents = if self.is_json() {
+            serde_json::to_string_pretty(&v).map_err(|e| malformed(&self.path, e.to_string()))?
+        } else {
+            serde_yaml::to_string(&v).map_err(|e| malformed(&self.path, e.to_string()))?
+        };
+        let tmp = self.sibling(&format!("{}.tmp", std::process::id()));
+        trace!("writing {:?} via {:?}", self.path, tmp);
+        let mut file = File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl MigrationList for FileStorage {
+    fn all(&mut self) -> Result<Box<(dyn Iterator<Item = Migration> + 'static)>, Error> {
+        let migrations = self
+            .read()?
+            .migrations
+            .iter()
+            .map(|v| migration_from_value(&self.path, v))
+            .collect::<Result<Vec<Migration>, Error>>()?;
+        Ok(Box::new(migrations.into_iter()))
+    }
+}
+
+impl MigrationStorage for FileStorage {
+    #[cfg(test)]
+    fn reset(&mut self) -> Result<(), Error> {
+        log::trace!("Running file storage reset.");
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::Io(e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn add(&mut self, m: Migration) -> Result<(), Error> {
+        debug!("adding {} to {:?}", m.version(), self.path);
+        let mut doc = self.read()?;
+        let version = Some(m.version());
+        doc.migrations.retain(|v| version_of(v) != version);
+        doc.migrations.push(migration_to_value(&m));
+        // Versions are timestamps of equal length, so sort as strings
+        doc.migrations.sort_by_key(version_of);
+        self.write(doc)
+    }
+
+    fn remove(&mut self, m: Migration) -> Result<(), Error> {
+        debug!("removing {} from {:?}", m.version(), self.path);
+        let mut doc = self.read()?;
+        let version = Some(m.version());
+        let before = doc.migrations.len();
+        doc.migrations.retain(|v| version_of(v) != version);
+        if doc.migrations.len() == before {
+            return Err(Error::MigrationNotFound {
+                version: m.version(),
+            });
+        }
+        self.write(doc)
+    }
+
+    // The lock is taken on a file beside the storage, rather than on the
+    // storage itself, as every write replaces that. Should this process die
+    // the operating system releases the lock with it.
+    fn lock(&mut self, timeout: Duration) -> Result<(), Error> {
+        let lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.sibling("lock"))?;
+        let started = Instant::now();
+        loop {
+            match lock.try_lock_exclusive() {
+                Ok(_) => {
+                    self.lock = Some(lock);
+                    return Ok(());
+                }
+                Err(e) if e.kind() != fs2::lock_contended_error().kind() => {
+                    return Err(Error::Io(e))
+                }
+                Err(_) if started.elapsed() >= timeout => {
+                    return Err(Error::LockTimeout { timeout })
+                }
+                Err(_) => std::thread::sleep(LOCK_POLL_INTERVAL),
+            }
+        }
+    }
+
+    fn unlock(&mut self) -> Result<(), Error> {
+        match self.lock.take() {
+            Some(lock) => Ok(lock.unlock()?),
+            None => Ok(()),
+        }
+    }
+
+    fn record(&mut self, entry: HistoryEntry) -> Result<(), Error> {
+        let mut doc = self.read()?;
+        doc.history.push(history_entry_to_value(&entry));
+        self.write(doc)
+    }
+
+    fn history(&mut self) -> Result<Vec<HistoryEntry>, Error> {
+        self.read()?
+            .history
+            .iter()
+            .map(|v| history_entry_from_value(&self.path, v))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage(dir: &Path, ext: &str) -> FileStorage {
+        FileStorage {
+            path: dir.join(format!("mitre_test.{}", ext)),
+            lock: None {},
+        }
+    }
+
+    fn migration() -> Migration {
+        Migration {
+            date_time: chrono::NaiveDateTime::parse_from_str("20210503213400", FORMAT_STR).unwrap(),
+            steps: std::array::IntoIter::new([(
+                Direction::Up,
+                MigrationStep {
+                    path: PathBuf::from("/foo/up.es.post"),
+                    source: String::from("{}"),
+                },
+            )])
+            .collect(),
+            flags: crate::reserved::flags_from_str_flags("data"),
+            built_in: false,
+            configuration_name: String::from("searchdb"),
+        }
+    }
+
+    #[test]
+    fn writes_yaml_or_json_by_extension() -> Result<(), String> {
+        let dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+        for (ext, starts_with) in &[("yml", "---"), ("json", "{")] {
+            let mut s = storage(dir.path(), ext);
+            s.add(migration()).map_err(|e| format!("{:?}", e))?;
+            let contents = fs::read_to_string(&s.path).map_err(|e| e.to_string())?;
+            assert!(contents.starts_with(starts_with), "{}", contents);
+            assert_eq!(s.all().map_err(|e| format!("{:?}", e))?.count(), 1);
+            // Nothing is left behind by the write-then-rename
+            assert!(!s.sibling(&format!("{}.tmp", std::process::id())).exists());
+            s.reset().map_err(|e| format!("{:?}", e))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn lock_is_exclusive_across_instances() -> Result<(), String> {
+        let dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+        let mut a = storage(dir.path(), "yml");
+        let mut b = FileStorage {
+            path: a.path.clone(),
+            lock: None {},
+        };
+        a.lock(Duration::from_secs(1))
+            .map_err(|e| format!("{:?}", e))?;
+        match b.lock(Duration::from_millis(10)) {
+            Err(Error::LockTimeout { .. }) => {}
+            other => return Err(format!("expected a lock timeout, got {:?}", other)),
+        }
+        a.unlock().map_err(|e| format!("{:?}", e))?;
+        b.lock(Duration::from_secs(1))
+            .map_err(|e| format!("{:?}", e))?;
+        b.unlock().map_err(|e| format!("{:?}", e))
+    }
+}
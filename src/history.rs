@@ -0,0 +1,62 @@
+use super::{Direction, Migration, MigrationResult};
+use chrono::prelude::*;
+
+/// One attempt to apply, or roll back, a migration as recorded in the
+/// append-only history kept by the migration storage. Unlike the migration
+/// state, which only knows what is currently applied, failed attempts and
+/// rolled back migrations are kept here too.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub version: String,
+    pub direction: Direction,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub outcome: String,
+    pub error: Option<String>,
+    pub hostname: String,
+    pub mitre_version: String,
+}
+
+impl HistoryEntry {
+    /// An entry for an attempt which started at `started_at` and has just
+    /// finished with `result`, on this host, by this version of Mitre.
+    pub fn new(
+        migration: &Migration,
+        direction: Direction,
+        started_at: DateTime<Utc>,
+        result: &MigrationResult,
+    ) -> Self {
+        let (outcome, error) = match result {
+            MigrationResult::Failure { reason } => ("Failure", Some(reason.clone())),
+            MigrationResult::AlreadyApplied => ("AlreadyApplied", None {}),
+            MigrationResult::Success => ("Success", None {}),
+            MigrationResult::NothingToDo => ("NothingToDo", None {}),
+            MigrationResult::IrreversibleMigration => ("IrreversibleMigration", None {}),
+            MigrationResult::SkippedDueToEarlierError => ("SkippedDueToEarlierError", None {}),
+        };
+        HistoryEntry {
+            version: migration.version(),
+            direction,
+            started_at,
+            finished_at: Utc::now(),
+            outcome: String::from(outcome),
+            error,
+            hostname: hostname(),
+            mitre_version: String::from(env!("CARGO_PKG_VERSION")),
+        }
+    }
+
+    pub fn duration(&self) -> chrono::Duration {
+        self.finished_at - self.started_at
+    }
+}
+
+fn hostname() -> String {
+    match hostname::get() {
+        Ok(name) => name.to_string_lossy().into_owned(),
+        Err(e) => {
+            log::warn!("could not determine hostname for the history {:?}", e);
+            String::from("unknown")
+        }
+    }
+}
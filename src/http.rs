@@ -0,0 +1,311 @@
+use super::{
+    driver, migrations::MigrationStep, Driver, DriverResult, Error, MigrationTemplate, NamedDriver,
+    RunnerConfiguration, RunnerMeta,
+};
+use indoc::indoc;
+use log::{debug, info, trace};
+
+// Stands for the configured protocol, host and port at the start of a path,
+// e.g "${hostname}/_cluster/health"
+const HOST_PLACEHOLDER: &str = "${hostname}";
+
+/// Runs `.get`, `.post`, `.patch` and `.delete` migrations, with the method
+/// taken from the extension, e.g. against Elasticsearch or any other HTTP
+/// API. Migrations are requests without the method:
+///
+/// ```text
+/// /my-index/_settings
+/// Content-Type: application/json
+///
+/// {"index": {"number_of_replicas": 2}}
+/// ```
+///
+/// The first line is the path, relative to the configured `protocol`,
+/// `ip_or_hostname` and `port`, or a full URL. The path may start with
+/// `${hostname}`, or `{{base_url}}`, which stand for those too. Headers
+/// follow, one per line, until an empty line, and everything after that is
+/// the body.
+pub struct Http {
+    client: reqwest::blocking::Client,
+    config: RunnerConfiguration,
+}
+
+#[derive(Debug, PartialEq)]
+struct Request {
+    target: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+fn parse_request(source: &str) -> Result<Request, String> {
+    let mut lines = source.lines().skip_while(|line| line.trim().is_empty());
+    let target = match lines.next() {
+        Some(line) => String::from(line.trim()),
+        None => return Err(String::from("no path or URL on the first line")),
+    };
+    let mut headers = vec![];
+    for line in lines.by_ref() {
+        if line.trim().is_empty() {
+            break;
+        }
+        match line.find(':') {
+            Some(i) => headers.push((
+                String::from(line[..i].trim()),
+                String::from(line[i + 1..].trim()),
+            )),
+            None => return Err(format!("expected a header, got {:?}", line)),
+        }
+    }
+    Ok(Request {
+        target,
+        headers,
+        body: lines.collect::<Vec<&str>>().join("\n"),
+    })
+}
+
+fn method(ms: &MigrationStep) -> Result<reqwest::Method, Error> {
+    let ext = ms
+        .path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    match ext.as_deref() {
+        Some("get") => Ok(reqwest::Method::GET),
+        Some("post") => Ok(reqwest::Method::POST),
+        Some("patch") => Ok(reqwest::Method::PATCH),
+        Some("delete") => Ok(reqwest::Method::DELETE),
+        _ => Err(Error::QueryFailed {
+            reason: None {},
+            msg: format!(
+                "{}: the extension must be one of get, post, patch or delete",
+                ms.path.display()
+            ),
+        }),
+    }
+}
+
+impl Http {
+    pub fn new(config: RunnerConfiguration) -> Result<Self, Error> {
+        info!("using http driver for {:?}", config.ip_or_hostname);
+        match reqwest::blocking::Client::builder().build() {
+            Ok(client) => Ok(Http { client, config }),
+            Err(e) => Err(Error::ConnectionError {
+                msg: format!("Creating HTTP client: {}", e),
+            }),
+        }
+    }
+
+    // e.g http://127.0.0.1:9200, without a trailing slash
+    fn base_url(&self) -> String {
+        let protocol = self.config.protocol.as_deref().unwrap_or("http");
+        let host = self.config.ip_or_hostname.as_deref().unwrap_or("127.0.0.1");
+        match self.config.port {
+            Some(port) => format!("{}://{}:{}", protocol, host, port),
+            None => format!("{}://{}", protocol, host),
+        }
+    }
+
+    fn url(&self, target: &str) -> String {
+        let target = target.strip_prefix(HOST_PLACEHOLDER).unwrap_or(target);
+        if target.starts_with("http://") || target.starts_with("https://") {
+            String::from(target)
+        } else {
+            format!("{}/{}", self.base_url(), target.trim_start_matches('/'))
+        }
+    }
+
+    fn template_ctx(&self) -> mustache::Data {
+        mustache::MapBuilder::new()
+            .insert_str("base_url", self.base_url())
+            .insert_str("index", self.config.index.clone().unwrap_or_default())
+            .build()
+    }
+
+    fn render_request(&self, query: &str) -> Result<String, Error> {
+        driver::render_template(query, &self.template_ctx(), "request")
+    }
+}
+
+impl Driver for Http {
+    fn run(&mut self, ms: &MigrationStep) -> Result<DriverResult, Error> {
+        let method = method(ms)?;
+        let request =
+            parse_request(&self.render_request(&ms.source)?).map_err(|e| Error::QueryFailed {
+                reason: None {},
+                msg: format!("{}: {}", ms.path.display(), e),
+            })?;
+        let url = self.url(&request.target);
+        trace!("{} {} {:?}", method, url, request.headers);
+
+        let mut builder = self.client.request(method.clone(), &url);
+        if let Some(username) = &self.config.username {
+            builder = builder.basic_auth(username, self.config.password.as_ref());
+        }
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        if !request.body.is_empty() {
+            builder = builder.body(request.body);
+        }
+        let response = match builder.send() {
+            Ok(response) => response,
+            Err(e) => {
+                return Err(Error::QueryFailed {
+                    reason: None {},
+                    msg: format!("{} {} failed: {}", method, url, e),
+                })
+            }
+        };
+        let status = response.status();
+        let body = response.text().unwrap_or_default();
+        if !status.is_success() {
+            return Err(Error::QueryFailed {
+                reason: None {},
+                msg: format!("{} {} returned {}: {}", method, url, status, body),
+            });
+        }
+        debug!("{} {} returned {}: {}", method, url, status, body);
+        Ok(DriverResult::Success)
+    }
+
+    fn render(&self, ms: &MigrationStep) -> Result<String, Error> {
+        self.render_request(&ms.source)
+    }
+
+    fn migration_template(&self) -> MigrationTemplate {
+        MigrationTemplate {
+            extension: "post",
+            up: indoc!(
+                r#"
+                /your-index/_doc
+                Content-Type: application/json
+
+                {"your": "document"}
+                "#
+            ),
+            down: None {},
+        }
+    }
+
+    fn meta(&self) -> RunnerMeta<'static> {
+        crate::reserved::runner_by_name(crate::reserved::HTTP).expect("reserved word not found")
+    }
+}
+
+impl NamedDriver for Http {
+    fn name() -> &'static str {
+        "http"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+
+    fn config(port: Option<u16>) -> RunnerConfiguration {
+        RunnerConfiguration {
+            _driver: String::from("http"),
+            ip_or_hostname: Some(String::from("127.0.0.1")),
+            port,
+            ..Default::default()
+        }
+    }
+
+    fn step(path: &str, source: &str) -> MigrationStep {
+        MigrationStep {
+            path: PathBuf::from(path),
+            source: String::from(source),
+        }
+    }
+
+    // Accepts a single connection, answers it with `response`, and sends
+    // back the request line it was given
+    fn serve_once(response: &'static str) -> (u16, std::sync::mpsc::Receiver<String>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 4096];
+            let n = stream.read(&mut buf).unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..n]).into_owned();
+            stream.write_all(response.as_bytes()).unwrap();
+            let _ = tx.send(String::from(request.lines().next().unwrap_or_default()));
+        });
+        (port, rx)
+    }
+
+    #[test]
+    fn parses_target_headers_and_body() {
+        assert_eq!(
+            parse_request(
+                "\n/my-index/_settings\nContent-Type: application/json\n\n{\n  \"a\": 1\n}"
+            ),
+            Ok(Request {
+                target: String::from("/my-index/_settings"),
+                headers: vec![(
+                    String::from("Content-Type"),
+                    String::from("application/json")
+                )],
+                body: String::from("{\n  \"a\": 1\n}"),
+            })
+        );
+        assert!(parse_request("/my-index\nnot a header").is_err());
+        assert!(parse_request("\n\n").is_err());
+    }
+
+    #[test]
+    fn builds_urls_from_the_config() -> Result<(), String> {
+        let http = Http::new(config(Some(9200))).map_err(|e| format!("{:?}", e))?;
+        assert_eq!(
+            http.url("/_cluster/health"),
+            "http://127.0.0.1:9200/_cluster/health"
+        );
+        assert_eq!(http.url("https://example.com/x"), "https://example.com/x");
+        assert_eq!(
+            http.url("${hostname}/_cluster/health"),
+            "http://127.0.0.1:9200/_cluster/health"
+        );
+        let http = Http::new(config(None {})).map_err(|e| format!("{:?}", e))?;
+        assert_eq!(
+            http.url("_cluster/health"),
+            "http://127.0.0.1/_cluster/health"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn treats_non_2xx_as_failure_with_the_response_body() -> Result<(), String> {
+        let (port, _) = serve_once(
+            "HTTP/1.1 400 Bad Request\r\nContent-Length: 12\r\nConnection: close\r\n\r\nindex exists",
+        );
+        let mut http = Http::new(config(Some(port))).map_err(|e| format!("{:?}", e))?;
+        match http.run(&step("/foo/1_create.es.post", "/my-index\n\n{}")) {
+            Err(Error::QueryFailed { msg, .. })
+                if msg.contains("400") && msg.contains("index exists") =>
+            {
+                Ok(())
+            }
+            Err(e) => Err(format!("unexpected error {:?}", e)),
+            Ok(_) => Err(String::from("a 400 response was treated as success")),
+        }
+    }
+
+    #[test]
+    fn takes_the_method_from_the_extension() -> Result<(), String> {
+        let (port, request_line) =
+            serve_once("HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        let mut http = Http::new(config(Some(port))).map_err(|e| format!("{:?}", e))?;
+        http.run(&step("/foo/up.delete", "/my-index"))
+            .map_err(|e| format!("{:?}", e))?;
+        assert_eq!(
+            request_line.recv().map_err(|e| e.to_string())?,
+            "DELETE /my-index HTTP/1.1"
+        );
+        match method(&step("/foo/up.put", "/my-index")) {
+            Err(Error::QueryFailed { .. }) => Ok(()),
+            other => Err(format!("expected an error, got {:?}", other)),
+        }
+    }
+}
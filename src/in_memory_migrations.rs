@@ -1,12 +1,28 @@
-use super::{Error, Migration, MigrationList, MigrationStorage};
+use super::{Error, HistoryEntry, Migration, MigrationList, MigrationStorage};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
 
 pub struct InMemoryMigrations {
     pub m: Vec<Migration>,
+    pub history: Vec<HistoryEntry>,
+    // Whether the storage is locked, and a condition to wait on it being
+    // released.
+    lock: (Mutex<bool>, Condvar),
 }
 
 impl InMemoryMigrations {
     pub fn new() -> Self {
-        InMemoryMigrations { m: vec![] }
+        Self::from(vec![])
+    }
+}
+
+impl From<Vec<Migration>> for InMemoryMigrations {
+    fn from(m: Vec<Migration>) -> Self {
+        InMemoryMigrations {
+            m,
+            history: vec![],
+            lock: (Mutex::new(false), Condvar::new()),
+        }
     }
 }
 
@@ -26,6 +42,7 @@ impl MigrationStorage for InMemoryMigrations {
     #[cfg(test)]
     fn reset(&mut self) -> Result<(), Error> {
         self.m = vec![];
+        self.history = vec![];
         Ok(())
     }
     fn add(&mut self, m: Migration) -> Result<(), Error> {
@@ -33,8 +50,41 @@ impl MigrationStorage for InMemoryMigrations {
         Ok(())
     }
     fn remove(&mut self, m: Migration) -> Result<(), Error> {
-        let index = self.m.iter().position(|x| *x == m).unwrap();
-        self.m.remove(index);
+        // Match on the timestamp only, the steps on disk may not be
+        // byte-for-byte what was stored when the migration was added.
+        match self.m.iter().position(|x| x.date_time == m.date_time) {
+            Some(index) => {
+                self.m.remove(index);
+                Ok(())
+            }
+            None => Err(Error::MigrationNotFound {
+                version: m.version(),
+            }),
+        }
+    }
+    fn lock(&mut self, timeout: Duration) -> Result<(), Error> {
+        let (locked, released) = &self.lock;
+        let locked = locked.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut locked, _) = released
+            .wait_timeout_while(locked, timeout, |locked| *locked)
+            .unwrap_or_else(|e| e.into_inner());
+        if *locked {
+            return Err(Error::LockTimeout { timeout });
+        }
+        *locked = true;
         Ok(())
     }
+    fn unlock(&mut self) -> Result<(), Error> {
+        let (locked, released) = &self.lock;
+        *locked.lock().unwrap_or_else(|e| e.into_inner()) = false;
+        released.notify_one();
+        Ok(())
+    }
+    fn record(&mut self, entry: HistoryEntry) -> Result<(), Error> {
+        self.history.push(entry);
+        Ok(())
+    }
+    fn history(&mut self) -> Result<Vec<HistoryEntry>, Error> {
+        Ok(self.history.clone())
+    }
 }
@@ -0,0 +1,850 @@
+use super::{
+    driver, migrations::MigrationStep, Driver, DriverResult, Error, MigrationTemplate, NamedDriver,
+    RunnerConfiguration, RunnerMeta,
+};
+use indoc::indoc;
+use log::{debug, info, trace};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+// TCP ports are unsigned 16 bit ints
+// https://tools.ietf.org/html/rfc793#section-3.1
+const KAFKA_DEFAULT_PORT: u16 = 9092;
+
+const CLIENT_ID: &str = "mitre";
+
+// How long the brokers have to create topics or acknowledge records, and
+// how long we wait for them to answer at all.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs `.kafka` migrations, for the topics (and their configuration) and
+/// seed records which applications expect to find:
+///
+/// ```text
+/// # one operation per line
+/// create topic orders partitions 6 replication 3 retention.ms=604800000
+/// alter topic payments cleanup.policy=compact
+///
+/// # records follow produce, one per line, until a blank line
+/// produce currencies
+/// {"code": "EUR"}
+///
+/// # keyed records are a key, a space, and the value
+/// produce countries keyed
+/// de {"name": "Germany"}
+/// ```
+///
+/// Lines starting with # are comments. As with Redis nothing is rolled back,
+/// the operations before a failing one stay applied, so the error names the
+/// line of the failing operation.
+pub struct Kafka {
+    cluster: Box<dyn Cluster>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Operation {
+    CreateTopic {
+        topic: String,
+        partitions: i32,
+        replication: i16,
+        configs: Vec<(String, String)>,
+    },
+    AlterTopic {
+        topic: String,
+        configs: Vec<(String, String)>,
+    },
+    Produce {
+        topic: String,
+        records: Vec<(Option<String>, String)>,
+    },
+}
+
+// What the driver needs of a cluster, so that an in-process stand-in can
+// take the place of the brokers in tests.
+trait Cluster {
+    fn create_topic(
+        &mut self,
+        topic: &str,
+        partitions: i32,
+        replication: i16,
+        configs: &[(String, String)],
+    ) -> Result<(), String>;
+    fn alter_topic(&mut self, topic: &str, configs: &[(String, String)]) -> Result<(), String>;
+    fn produce(&mut self, topic: &str, records: &[(Option<String>, String)]) -> Result<(), String>;
+}
+
+fn config_entry(word: &str) -> Result<(String, String), String> {
+    match word.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((String::from(name), String::from(value))),
+        _ => Err(format!(
+            "expected a config entry (name=value), got {:?}",
+            word
+        )),
+    }
+}
+
+fn create_topic(topic: &str, words: &[&str]) -> Result<Operation, String> {
+    let mut partitions = None {};
+    let mut replication = None {};
+    let mut configs = vec![];
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        match word.to_lowercase().as_str() {
+            "partitions" => match words.next().map(|n| n.parse::<i32>()) {
+                Some(Ok(n)) if n > 0 => partitions = Some(n),
+                _ => return Err(String::from("partitions must be followed by a number")),
+            },
+            "replication" => match words.next().map(|n| n.parse::<i16>()) {
+                Some(Ok(n)) if n > 0 => replication = Some(n),
+                _ => return Err(String::from("replication must be followed by a number")),
+            },
+            _ => configs.push(config_entry(word)?),
+        }
+    }
+    match (partitions, replication) {
+        (Some(partitions), Some(replication)) => Ok(Operation::CreateTopic {
+            topic: String::from(topic),
+            partitions,
+            replication,
+            configs,
+        }),
+        _ => Err(format!(
+            "create topic {} needs both partitions and replication",
+            topic
+        )),
+    }
+}
+
+// The operations in a migration, with the (1-based) line each starts on.
+// Errors carry the line number too.
+fn parse_operations(source: &str) -> Result<Vec<(usize, Operation)>, (usize, String)> {
+    let mut operations = vec![];
+    // The produce operation whose records are being read, and whether they are keyed
+    let mut producing: Option<(usize, Operation, bool)> = None {};
+    for (n, line) in source.lines().enumerate() {
+        let n = n + 1;
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        if let Some((_, Operation::Produce { records, .. }, keyed)) = &mut producing {
+            if !line.is_empty() {
+                records.push(match (*keyed, line.split_once(char::is_whitespace)) {
+                    (false, _) => (None {}, String::from(line)),
+                    (true, Some((key, value))) => {
+                        (Some(String::from(key)), String::from(value.trim_start()))
+                    }
+                    (true, None) => {
+                        return Err((n, format!("expected a key and a value, got {:?}", line)))
+                    }
+                });
+                continue;
+            }
+        }
+        if line.is_empty() {
+            // Which also ends the records of a produce
+            if let Some((start, operation, _)) = producing.take() {
+                operations.push((start, operation));
+            }
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let keywords: Vec<String> = words.iter().take(2).map(|w| w.to_lowercase()).collect();
+        let keywords: Vec<&str> = keywords.iter().map(|w| w.as_str()).collect();
+        match (keywords.as_slice(), words.len()) {
+            (["create", "topic"], len) if len > 2 => {
+                operations.push((n, create_topic(words[2], &words[3..]).map_err(|e| (n, e))?))
+            }
+            (["alter", "topic"], len) if len > 3 => {
+                let configs = words[3..]
+                    .iter()
+                    .map(|word| config_entry(word))
+                    .collect::<Result<Vec<(String, String)>, String>>()
+                    .map_err(|e| (n, e))?;
+                operations.push((
+                    n,
+                    Operation::AlterTopic {
+                        topic: String::from(words[2]),
+                        configs,
+                    },
+                ))
+            }
+            (["produce", _], 2) | (["produce", _], 3) => {
+                let keyed = match words.get(2).map(|w| w.to_lowercase()) {
+                    None => false,
+                    Some(w) if w == "keyed" => true,
+                    Some(w) => return Err((n, format!("expected keyed, got {:?}", w))),
+                };
+                let produce = Operation::Produce {
+                    topic: String::from(words[1]),
+                    records: vec![],
+                };
+                producing = Some((n, produce, keyed));
+            }
+            _ => {
+                return Err((
+                    n,
+                    format!(
+                        "expected create topic, alter topic (with configs) or produce, got {:?}",
+                        line
+                    ),
+                ))
+            }
+        }
+    }
+    if let Some((start, operation, _)) = producing {
+        operations.push((start, operation));
+    }
+    for (n, operation) in &operations {
+        if let Operation::Produce { records, .. } = operation {
+            if records.is_empty() {
+                return Err((*n, String::from("no records to produce")));
+            }
+        }
+    }
+    if operations.is_empty() {
+        return Err((1, String::from("no operations found")));
+    }
+    Ok(operations)
+}
+
+// Just enough of Kafka's protocol for the admin requests, which the kafka
+// crate doesn't have. https://kafka.apache.org/protocol
+const METADATA: (i16, i16) = (3, 1);
+const CREATE_TOPICS: (i16, i16) = (19, 2);
+const INCREMENTAL_ALTER_CONFIGS: (i16, i16) = (44, 0);
+
+const TOPIC_RESOURCE: i8 = 2;
+const SET_CONFIG: i8 = 0;
+
+fn put_i8(buf: &mut Vec<u8>, v: i8) {
+    buf.extend_from_slice(&v.to_be_bytes())
+}
+
+fn put_i16(buf: &mut Vec<u8>, v: i16) {
+    buf.extend_from_slice(&v.to_be_bytes())
+}
+
+fn put_i32(buf: &mut Vec<u8>, v: i32) {
+    buf.extend_from_slice(&v.to_be_bytes())
+}
+
+// Strings are prefixed with their length as an i16, longer ones can't be sent
+fn put_str(buf: &mut Vec<u8>, s: &str) -> Result<(), String> {
+    if s.len() > i16::MAX as usize {
+        return Err(format!(
+            "{:?}... is longer than the {} bytes a Kafka string may be",
+            s.chars().take(32).collect::<String>(),
+            i16::MAX
+        ));
+    }
+    put_i16(buf, s.len() as i16);
+    buf.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.buf.len() < n {
+            return Err(String::from("the broker's response was cut short"));
+        }
+        let (taken, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(taken)
+    }
+
+    fn i8(&mut self) -> Result<i8, String> {
+        Ok(self.take(1)?[0] as i8)
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        let b = self.take(2)?;
+        Ok(i16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        let b = self.take(4)?;
+        Ok(i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn nullable_string(&mut self) -> Result<Option<String>, String> {
+        match self.i16()? {
+            -1 => Ok(None {}),
+            len => Ok(Some(
+                String::from_utf8_lossy(self.take(len as usize)?).into_owned(),
+            )),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        Ok(self.nullable_string()?.unwrap_or_default())
+    }
+}
+
+// Sends a request, and returns the body of the response
+fn call(addr: &str, api: (i16, i16), body: &[u8]) -> Result<Vec<u8>, String> {
+    let failed = |e: std::io::Error| format!("talking to {}: {}", addr, e);
+    let mut stream = TcpStream::connect(addr).map_err(failed)?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(failed)?;
+
+    let mut request = vec![];
+    put_i16(&mut request, api.0);
+    put_i16(&mut request, api.1);
+    put_i32(&mut request, 1); // correlation id, there's one request per connection
+    put_str(&mut request, CLIENT_ID)?;
+    request.extend_from_slice(body);
+    let mut framed = (request.len() as i32).to_be_bytes().to_vec();
+    framed.extend_from_slice(&request);
+    stream.write_all(&framed).map_err(failed)?;
+
+    let mut size = [0; 4];
+    stream.read_exact(&mut size).map_err(failed)?;
+    let mut response = vec![0; i32::from_be_bytes(size).max(0) as usize];
+    stream.read_exact(&mut response).map_err(failed)?;
+    // Without the correlation id
+    Reader { buf: &response }.take(4)?;
+    Ok(response.split_off(4))
+}
+
+fn check(code: i16, message: Option<String>) -> Result<(), String> {
+    let name = match code {
+        0 => return Ok(()),
+        3 => "unknown topic",
+        36 => "topic already exists",
+        37 => "invalid partitions",
+        38 => "invalid replication factor",
+        40 => "invalid config",
+        41 => "not the controller",
+        _ => "error",
+    };
+    match message {
+        Some(message) => Err(format!("{} (error code {}): {}", name, code, message)),
+        None => Err(format!("{} (error code {})", name, code)),
+    }
+}
+
+struct Brokers {
+    bootstrap: String,
+    // Topics are created by the controller
+    controller: String,
+}
+
+impl Brokers {
+    // Connects to the bootstrap broker to find the controller
+    fn connect(bootstrap: String) -> Result<Brokers, String> {
+        let mut body = vec![];
+        put_i32(&mut body, 0); // no topics, only the brokers
+        let response = call(&bootstrap, METADATA, &body)?;
+        let mut r = Reader { buf: &response };
+        let mut brokers = HashMap::new();
+        for _ in 0..r.i32()? {
+            let id = r.i32()?;
+            let host = r.string()?;
+            let port = r.i32()?;
+            let _rack = r.nullable_string()?;
+            brokers.insert(id, format!("{}:{}", host, port));
+        }
+        let controller_id = r.i32()?;
+        let controller = match brokers.remove(&controller_id) {
+            Some(controller) => controller,
+            None => {
+                return Err(format!(
+                    "controller {} is not a known broker",
+                    controller_id
+                ))
+            }
+        };
+        debug!("kafka controller is {}", controller);
+        Ok(Brokers {
+            bootstrap,
+            controller,
+        })
+    }
+}
+
+impl Cluster for Brokers {
+    fn create_topic(
+        &mut self,
+        topic: &str,
+        partitions: i32,
+        replication: i16,
+        configs: &[(String, String)],
+    ) -> Result<(), String> {
+        let mut body = vec![];
+        put_i32(&mut body, 1);
+        put_str(&mut body, topic)?;
+        put_i32(&mut body, partitions);
+        put_i16(&mut body, replication);
+        put_i32(&mut body, 0); // no manual replica assignment
+        put_i32(&mut body, configs.len() as i32);
+        for (name, value) in configs {
+            put_str(&mut body, name)?;
+            put_str(&mut body, value)?;
+        }
+        put_i32(&mut body, TIMEOUT.as_millis() as i32);
+        put_i8(&mut body, 0); // not only validating
+
+        let response = call(&self.controller, CREATE_TOPICS, &body)?;
+        let mut r = Reader { buf: &response };
+        let _throttle_time = r.i32()?;
+        for _ in 0..r.i32()? {
+            let _topic = r.string()?;
+            let code = r.i16()?;
+            check(code, r.nullable_string()?)?;
+        }
+        Ok(())
+    }
+
+    fn alter_topic(&mut self, topic: &str, configs: &[(String, String)]) -> Result<(), String> {
+        // Incremental, so that configs not named here are left alone
+        let mut body = vec![];
+        put_i32(&mut body, 1);
+        put_i8(&mut body, TOPIC_RESOURCE);
+        put_str(&mut body, topic)?;
+        put_i32(&mut body, configs.len() as i32);
+        for (name, value) in configs {
+            put_str(&mut body, name)?;
+            put_i8(&mut body, SET_CONFIG);
+            put_str(&mut body, value)?;
+        }
+        put_i8(&mut body, 0); // not only validating
+
+        let response = call(&self.bootstrap, INCREMENTAL_ALTER_CONFIGS, &body)?;
+        let mut r = Reader { buf: &response };
+        let _throttle_time = r.i32()?;
+        for _ in 0..r.i32()? {
+            let code = r.i16()?;
+            check(code, r.nullable_string()?)?;
+            let _resource_type = r.i8()?;
+            let _resource_name = r.string()?;
+        }
+        Ok(())
+    }
+
+    fn produce(&mut self, topic: &str, records: &[(Option<String>, String)]) -> Result<(), String> {
+        // A producer per operation, it only knows the topics which existed
+        // when it was created.
+        let mut producer = kafka::producer::Producer::from_hosts(vec![self.bootstrap.clone()])
+            .with_client_id(String::from(CLIENT_ID))
+            .with_ack_timeout(TIMEOUT)
+            .with_required_acks(kafka::producer::RequiredAcks::All)
+            .create()
+            .map_err(|e| format!("connecting a producer to {}: {}", self.bootstrap, e))?;
+        for (key, value) in records {
+            // An empty key is sent as no key at all
+            let record = kafka::producer::Record::from_key_value(
+                topic,
+                key.as_deref().unwrap_or_default(),
+                value.as_str(),
+            );
+            producer
+                .send(&record)
+                .map_err(|e| format!("producing {:?} to {}: {}", value, topic, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl Kafka {
+    pub fn new(config: RunnerConfiguration) -> Result<Self, Error> {
+        let bootstrap = format!(
+            "{}:{}",
+            config.ip_or_hostname.as_deref().unwrap_or("127.0.0.1"),
+            config.port.unwrap_or(KAFKA_DEFAULT_PORT)
+        );
+        info!("connecting to kafka {}", bootstrap);
+        match Brokers::connect(bootstrap) {
+            Ok(brokers) => Ok(Kafka {
+                cluster: Box::new(brokers),
+            }),
+            Err(e) => Err(Error::ConnectionError {
+                msg: format!("Connecting to Kafka: {}", e),
+            }),
+        }
+    }
+
+    fn render_operations(&self, source: &str) -> Result<String, Error> {
+        driver::render_template(source, &mustache::MapBuilder::new().build(), "operations")
+    }
+}
+
+impl Driver for Kafka {
+    fn run(&mut self, ms: &MigrationStep) -> Result<DriverResult, Error> {
+        let failed = |line: usize, msg: String| Error::QueryFailed {
+            reason: None {},
+            msg: format!("{}:{}: {}", ms.path.display(), line, msg),
+        };
+        let operations = parse_operations(&self.render_operations(&ms.source)?)
+            .map_err(|(n, e)| failed(n, e))?;
+        for (n, operation) in operations {
+            trace!("running kafka operation {:?} from line {}", operation, n);
+            match &operation {
+                Operation::CreateTopic {
+                    topic,
+                    partitions,
+                    replication,
+                    configs,
+                } => self
+                    .cluster
+                    .create_topic(topic, *partitions, *replication, configs),
+                Operation::AlterTopic { topic, configs } => {
+                    self.cluster.alter_topic(topic, configs)
+                }
+                Operation::Produce { topic, records } => self.cluster.produce(topic, records),
+            }
+            .map_err(|e| failed(n, e))?;
+        }
+        Ok(DriverResult::Success)
+    }
+
+    fn render(&self, ms: &MigrationStep) -> Result<String, Error> {
+        self.render_operations(&ms.source)
+    }
+
+    fn migration_template(&self) -> MigrationTemplate {
+        MigrationTemplate {
+            extension: "kafka",
+            up: indoc!(
+                "
+                create topic your_topic partitions 1 replication 1
+                "
+            ),
+            // Topics are not deleted, there is no operation for that
+            down: None {},
+        }
+    }
+
+    fn meta(&self) -> RunnerMeta<'static> {
+        crate::reserved::runner_by_name(crate::reserved::KAFKA).expect("reserved word not found")
+    }
+}
+
+impl NamedDriver for Kafka {
+    fn name() -> &'static str {
+        "kafka"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+    use std::sync::mpsc::{channel, Receiver};
+
+    // Topics with their partitions, replication and configs, and the
+    // records produced to them.
+    #[derive(Default)]
+    struct StandIn {
+        topics: HashMap<String, (i32, i16, HashMap<String, String>)>,
+        records: Vec<(String, Option<String>, String)>,
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedStandIn(Rc<RefCell<StandIn>>);
+
+    impl Cluster for SharedStandIn {
+        fn create_topic(
+            &mut self,
+            topic: &str,
+            partitions: i32,
+            replication: i16,
+            configs: &[(String, String)],
+        ) -> Result<(), String> {
+            let mut cluster = self.0.borrow_mut();
+            if cluster.topics.contains_key(topic) {
+                return check(36, None {});
+            }
+            let configs = configs.iter().cloned().collect();
+            cluster
+                .topics
+                .insert(String::from(topic), (partitions, replication, configs));
+            Ok(())
+        }
+
+        fn alter_topic(&mut self, topic: &str, configs: &[(String, String)]) -> Result<(), String> {
+            match self.0.borrow_mut().topics.get_mut(topic) {
+                Some((_, _, existing)) => {
+                    existing.extend(configs.iter().cloned());
+                    Ok(())
+                }
+                None => check(3, None {}),
+            }
+        }
+
+        fn produce(
+            &mut self,
+            topic: &str,
+            records: &[(Option<String>, String)],
+        ) -> Result<(), String> {
+            let mut cluster = self.0.borrow_mut();
+            if !cluster.topics.contains_key(topic) {
+                return check(3, None {});
+            }
+            for (key, value) in records {
+                cluster
+                    .records
+                    .push((String::from(topic), key.clone(), value.clone()));
+            }
+            Ok(())
+        }
+    }
+
+    fn step(source: &str) -> MigrationStep {
+        MigrationStep {
+            path: PathBuf::from("/foo/20210503213400_topics.events.kafka"),
+            source: String::from(source),
+        }
+    }
+
+    #[test]
+    fn parses_operations_and_record_blocks() {
+        let source = "# topics\ncreate topic orders partitions 6 replication 3 retention.ms=1000\nALTER TOPIC orders cleanup.policy=compact\n\nproduce countries keyed\nde {\"name\": \"Germany\"}\nfr  {\"name\": \"France\"}\n\nproduce currencies\n{\"code\": \"EUR\"}";
+        assert_eq!(
+            parse_operations(source),
+            Ok(vec![
+                (
+                    2,
+                    Operation::CreateTopic {
+                        topic: String::from("orders"),
+                        partitions: 6,
+                        replication: 3,
+                        configs: vec![(String::from("retention.ms"), String::from("1000"))],
+                    }
+                ),
+                (
+                    3,
+                    Operation::AlterTopic {
+                        topic: String::from("orders"),
+                        configs: vec![(String::from("cleanup.policy"), String::from("compact"))],
+                    }
+                ),
+                (
+                    5,
+                    Operation::Produce {
+                        topic: String::from("countries"),
+                        records: vec![
+                            (
+                                Some(String::from("de")),
+                                String::from("{\"name\": \"Germany\"}")
+                            ),
+                            (
+                                Some(String::from("fr")),
+                                String::from("{\"name\": \"France\"}")
+                            ),
+                        ],
+                    }
+                ),
+                (
+                    9,
+                    Operation::Produce {
+                        topic: String::from("currencies"),
+                        records: vec![(None {}, String::from("{\"code\": \"EUR\"}"))],
+                    }
+                ),
+            ])
+        );
+        for (source, line) in &[
+            ("create topic orders partitions 6", 1),
+            ("\ncreate topic orders partitions 6 replication 1 oops", 2),
+            ("alter topic orders", 1),
+            ("produce orders\n\nproduce other\n{}", 1),
+            ("produce orders keyed\nno-value", 2),
+            ("delete topic orders", 1),
+        ] {
+            assert_eq!(
+                parse_operations(source).err().map(|(n, _)| n),
+                Some(*line),
+                "{:?}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn runs_operations_in_order_and_names_the_failing_line() -> Result<(), String> {
+        let cluster = SharedStandIn::default();
+        let mut kafka = Kafka {
+            cluster: Box::new(cluster.clone()),
+        };
+        kafka
+            .run(&step(
+                "create topic orders partitions 3 replication 1\nalter topic orders retention.ms=1000\nproduce orders keyed\n1 {}\n2 {}",
+            ))
+            .map_err(|e| format!("{:?}", e))?;
+        {
+            let state = cluster.0.borrow();
+            let (partitions, replication, configs) = &state.topics["orders"];
+            assert_eq!((*partitions, *replication), (3, 1));
+            assert_eq!(configs["retention.ms"], "1000");
+            assert_eq!(
+                state.records,
+                vec![
+                    (
+                        String::from("orders"),
+                        Some(String::from("1")),
+                        String::from("{}")
+                    ),
+                    (
+                        String::from("orders"),
+                        Some(String::from("2")),
+                        String::from("{}")
+                    ),
+                ]
+            );
+        }
+        match kafka.run(&step(
+            "create topic payments partitions 1 replication 1\ncreate topic orders partitions 1 replication 1",
+        )) {
+            Err(Error::QueryFailed { msg, .. })
+                if msg.ends_with(":2: topic already exists (error code 36)") =>
+            {
+                assert!(cluster.0.borrow().topics.contains_key("payments"));
+                Ok(())
+            }
+            Err(e) => Err(format!("unexpected error {:?}", e)),
+            Ok(_) => Err(String::from("creating an existing topic succeeded")),
+        }
+    }
+
+    // Answers each connection in turn with one of `responses`, and passes
+    // on the requests, framed as they were sent.
+    fn stub_broker(responses: Vec<Vec<u8>>) -> (String, Receiver<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (tx, rx) = channel();
+        std::thread::spawn(move || {
+            for body in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut size = [0; 4];
+                stream.read_exact(&mut size).unwrap();
+                let mut request = vec![0; i32::from_be_bytes(size) as usize];
+                stream.read_exact(&mut request).unwrap();
+                // The correlation id is always 1
+                let mut response = ((body.len() + 4) as i32).to_be_bytes().to_vec();
+                response.extend_from_slice(&[0, 0, 0, 1]);
+                response.extend_from_slice(&body);
+                stream.write_all(&response).unwrap();
+                let _ = tx.send([&size[..], &request].concat());
+            }
+        });
+        (addr, rx)
+    }
+
+    fn brokers(addr: &str) -> Brokers {
+        Brokers {
+            bootstrap: String::from(addr),
+            controller: String::from(addr),
+        }
+    }
+
+    fn header(size: u8, api: (i16, i16)) -> Vec<u8> {
+        let mut header = vec![0, 0, 0, size];
+        put_i16(&mut header, api.0);
+        put_i16(&mut header, api.1);
+        header.extend_from_slice(&[0, 0, 0, 1, 0, 5]);
+        header.extend_from_slice(b"mitre");
+        header
+    }
+
+    #[test]
+    fn finds_the_controller_in_the_metadata() -> Result<(), String> {
+        let response = [
+            &[0, 0, 0, 2][..],
+            &[0, 0, 0, 1, 0, 1],
+            b"a",
+            &[0, 0, 0x23, 0x84, 0xff, 0xff],
+            &[0, 0, 0, 2, 0, 1],
+            b"b",
+            &[0, 0, 0x23, 0x85, 0, 4],
+            b"rack",
+            &[0, 0, 0, 2],
+            &[0, 0, 0, 0],
+        ]
+        .concat();
+        let (addr, requests) = stub_broker(vec![response]);
+        let brokers = Brokers::connect(addr)?;
+        assert_eq!(brokers.controller, "b:9093");
+        assert_eq!(
+            requests.recv().map_err(|e| e.to_string())?,
+            [header(19, METADATA), vec![0, 0, 0, 0]].concat()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn creates_topics_and_reports_their_errors() -> Result<(), String> {
+        let message = "Topic 'orders' already exists.";
+        let response = [
+            &[0, 0, 0, 0, 0, 0, 0, 1, 0, 6][..],
+            b"orders",
+            &[0, 36, 0, message.len() as u8],
+            message.as_bytes(),
+        ]
+        .concat();
+        let (addr, requests) = stub_broker(vec![response]);
+        let configs = vec![(String::from("retention.ms"), String::from("1000"))];
+        assert_eq!(
+            brokers(&addr).create_topic("orders", 3, 1, &configs),
+            Err(format!("topic already exists (error code 36): {}", message))
+        );
+        let expected = [
+            &header(66, CREATE_TOPICS)[..],
+            &[0, 0, 0, 1, 0, 6],
+            b"orders",
+            &[0, 0, 0, 3, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 12],
+            b"retention.ms",
+            &[0, 4],
+            b"1000",
+            &[0, 0, 0x75, 0x30, 0],
+        ]
+        .concat();
+        assert_eq!(requests.recv().map_err(|e| e.to_string())?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn alters_topics_and_reports_their_errors() -> Result<(), String> {
+        let response = [
+            &[0, 0, 0, 0, 0, 0, 0, 1, 0, 40, 0xff, 0xff, 2, 0, 6][..],
+            b"orders",
+        ]
+        .concat();
+        let (addr, requests) = stub_broker(vec![response]);
+        let configs = vec![(String::from("cleanup.policy"), String::from("compact"))];
+        assert_eq!(
+            brokers(&addr).alter_topic("orders", &configs),
+            Err(String::from("invalid config (error code 40)"))
+        );
+        let expected = [
+            &header(59, INCREMENTAL_ALTER_CONFIGS)[..],
+            &[0, 0, 0, 1, 2, 0, 6],
+            b"orders",
+            &[0, 0, 0, 1, 0, 14],
+            b"cleanup.policy",
+            &[0, 0, 7],
+            b"compact",
+            &[0],
+        ]
+        .concat();
+        assert_eq!(requests.recv().map_err(|e| e.to_string())?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_strings_too_long_for_their_length_prefix() {
+        let mut buf = vec![];
+        assert!(put_str(&mut buf, &"a".repeat(i16::MAX as usize)).is_ok());
+        buf.clear();
+        assert!(put_str(&mut buf, &"a".repeat(i16::MAX as usize + 1)).is_err());
+        assert!(buf.is_empty());
+    }
+}
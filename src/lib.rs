@@ -8,28 +8,90 @@ pub mod ui;
 
 mod driver;
 mod engine;
+mod file_storage;
+mod history;
 mod in_memory_migrations;
 mod migration_list;
 mod migration_storage;
+mod plugin;
+mod registry;
 mod runner;
 
+#[cfg(feature = "runner_bash")]
+mod bash;
+
+#[cfg(feature = "runner_elasticsearch")]
+mod elasticsearch;
+
+#[cfg(feature = "runner_python")]
+mod python;
+
+#[cfg(feature = "runner_rails")]
+mod rails;
+
+#[cfg(any(
+    feature = "runner_bash",
+    feature = "runner_python",
+    feature = "runner_rails"
+))]
+mod script;
+
+#[cfg(feature = "runner_http")]
+mod http;
+
+#[cfg(feature = "runner_kafka")]
+mod kafka;
+
 #[cfg(feature = "runner_mysql")]
 mod mysql;
 
+#[cfg(feature = "runner_postgres")]
+mod postgresql;
+
+#[cfg(feature = "runner_redis")]
+mod redis;
+
+#[cfg(feature = "runner_sqlite")]
+mod sqlite;
+
 // Public reuse defines the public API so that all other
 // modules can simply reuse these types without knowing
 // where they come from. The concept of Driver, DriverResult,
 // Migration, MigrationStateTuple, etc all belong here.
+#[cfg(feature = "runner_bash")]
+pub use self::bash::Bash;
+#[cfg(feature = "runner_elasticsearch")]
+pub use self::elasticsearch::Elasticsearch;
+#[cfg(feature = "runner_http")]
+pub use self::http::Http;
+#[cfg(feature = "runner_kafka")]
+pub use self::kafka::Kafka; // self:: required due to name conflict with kafka crate.
 pub use self::mysql::MySQL; // self:: required due to name conflict with MySQL crate.
+#[cfg(feature = "runner_postgres")]
+pub use self::postgresql::PostgreSql; // self:: for symmetry with MySQL, and the postgres crate.
+#[cfg(feature = "runner_python")]
+pub use self::python::Python;
+#[cfg(feature = "runner_rails")]
+pub use self::rails::Rails;
+#[cfg(feature = "runner_redis")]
+pub use self::redis::Redis; // self:: required due to name conflict with redis crate.
+#[cfg(feature = "runner_sqlite")]
+pub use self::sqlite::Sqlite;
 pub use config::{Configuration, ConfigurationName};
-pub use driver::{Driver, DriverResult, NamedDriver, StepDriver};
-pub use engine::Engine;
+pub use driver::{Driver, DriverResult, MigrationTemplate, NamedDriver};
+pub use engine::{
+    ApplyOptions, Engine, PlannedMigration, PlannedStep, TagFilter, Target, DEFAULT_LOCK_TIMEOUT,
+};
+pub use file_storage::FileStorage;
+pub use history::HistoryEntry;
 pub use in_memory_migrations::InMemoryMigrations;
 pub use migration_list::MigrationList;
 pub use migration_storage::MigrationStorage;
 pub use migrations::{
     Direction, Migration, MigrationStep, MigrationSteps, FORMAT_STR as TIMESTAMP_FORMAT_STR,
 };
+pub use plugin::PROTOCOL_VERSION as PLUGIN_PROTOCOL_VERSION;
+pub use registry::{register_driver, register_storage, DriverConstructor, StorageConstructor};
 pub use reserved::{Flag, RunnerMeta};
 pub use runner::{Configuration as RunnerConfiguration, MigrationResult, MigrationState};
 
@@ -37,7 +99,6 @@ pub use runner::{Configuration as RunnerConfiguration, MigrationResult, Migratio
 pub use driver::from_config as driver_from_config;
 pub use migration_list::from_disk as migration_list_from_disk;
 pub use migration_storage::from_config as migration_storage_from_config;
-pub use runner::from_config as runner_from_config;
 
 pub type MigrationStateTuple = (MigrationState, Migration);
 pub type MigrationResultTuple = (MigrationResult, Migration);
@@ -78,6 +139,53 @@ pub enum Error {
     NoSuchConfiguration {
         configuration_name: String,
     },
+
+    // MigrationNotFound a specific migration was asked for, but it isn't
+    // pending (or applied, when rolling back), or simply doesn't exist.
+    MigrationNotFound {
+        version: String,
+    },
+
+    // IrreversibleMigration the migration has no down step, so the
+    // operation asked for (e.g. redo) cannot be done.
+    IrreversibleMigration {
+        version: String,
+    },
+
+    // ModifiedMigrations applied migrations were edited on disk since they
+    // were applied, and strict mode forbids migrating until that is resolved.
+    ModifiedMigrations {
+        versions: Vec<String>,
+    },
+
+    // LockTimeout the migration storage lock is held by someone else, most
+    // likely a concurrent mitre run, and wasn't released within the timeout.
+    LockTimeout {
+        timeout: std::time::Duration,
+    },
+
+    // MalformedStorageFile the file storage exists, but could not be parsed,
+    // most likely because it was edited by hand.
+    MalformedStorageFile {
+        path: std::path::PathBuf,
+        msg: String,
+    },
+
+    // ScriptFailed a script migration exited non-zero, the exit code is
+    // None when it was killed by a signal.
+    ScriptFailed {
+        path: std::path::PathBuf,
+        exit_code: Option<i32>,
+        stdout: String,
+        stderr: String,
+    },
+
+    // ScriptTimedOut a script migration ran for longer than the configured
+    // timeout, and was killed.
+    ScriptTimedOut {
+        path: std::path::PathBuf,
+        timeout: std::time::Duration,
+    },
 }
 
 impl From<std::io::Error> for Error {
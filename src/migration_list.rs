@@ -17,6 +17,14 @@ impl MigrationList for &mut Box<dyn MigrationList> {
     }
 }
 
+// Implementation of MigrationList for &mut T so that a list can be lent
+// to the Engine and still be used by the caller afterwards.
+impl<T: MigrationList> MigrationList for &mut T {
+    fn all<'a>(&'a mut self) -> Result<Box<dyn Iterator<Item = Migration> + 'a>, Error> {
+        (**self).all()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     // Most all the bahaviour makes sense when tested
@@ -365,7 +365,12 @@ impl<'a> MigrationFinder {
             self.config.configured_drivers
         );
         match self.config.get(config_name) {
-            Some(config) => match runner_meta_by_name(&config._driver) {
+            // Drivers registered by applications, and plugins, aren't
+            // reserved words
+            Some(config) => match runner_meta_by_name(&config._driver)
+                .or_else(|| crate::registry::driver_meta(&config._driver))
+                .or_else(|| crate::plugin::runner_meta(&config._driver))
+            {
                 Some(runner_meta) => match runner_meta.exts.iter().find(|e| e == &&ext) {
                     Some(_) => Ok(RunnerMetaAndConfig {
                         meta: runner_meta,
@@ -1,15 +1,16 @@
-use super::{Configuration, Error, Migration, MigrationList, MySQL};
+use super::{Configuration, Error, HistoryEntry, Migration, MigrationList};
+use std::time::Duration;
 
-pub fn from_config(c: &Configuration) -> Result<impl MigrationStorage, Error> {
-    if let Some(config) = c.get("mitre") {
-        if config._driver.to_lowercase() == crate::reserved::MARIA_DB.to_lowercase() {
-            let storage = MySQL::new(config.clone())?;
-            Ok(storage)
-        } else {
-            Err(Error::UnsupportedDriverSpecified)
-        }
-    } else {
-        Err(Error::NoMitreConfigProvided)
+/// The migration storage for the `mitre` configuration, picked by its
+/// `_driver` from those registered with [`crate::register_storage`].
+pub fn from_config(c: &Configuration) -> Result<Box<dyn MigrationStorage>, Error> {
+    let config = match c.get("mitre") {
+        Some(config) => config,
+        None => return Err(Error::NoMitreConfigProvided),
+    };
+    match crate::registry::storage(&config._driver) {
+        Some(constructor) => constructor(config.clone()),
+        None => Err(Error::UnsupportedDriverSpecified),
     }
 }
 
@@ -18,11 +19,58 @@ pub trait MigrationStorage: MigrationList {
     fn reset(&mut self) -> Result<(), Error>;
 
     fn add(&mut self, _: Migration) -> Result<(), Error>;
+    /// Forget a stored migration, and its steps, matched by version. Returns
+    /// [`Error::MigrationNotFound`] when the version isn't stored.
     fn remove(&mut self, _: Migration) -> Result<(), Error>;
+
+    /// Take an exclusive lock, shared by every process using the same
+    /// storage, waiting at most `timeout` for a competing holder to release
+    /// it before returning [`Error::LockTimeout`].
+    fn lock(&mut self, timeout: Duration) -> Result<(), Error>;
+    fn unlock(&mut self) -> Result<(), Error>;
+
+    /// Append an attempt to the history, entries are never changed or
+    /// removed once recorded.
+    fn record(&mut self, _: HistoryEntry) -> Result<(), Error>;
+    /// Every recorded attempt, oldest first.
+    fn history(&mut self) -> Result<Vec<HistoryEntry>, Error>;
 }
 
 // Implementation of MigrationStorage for Box<MigrationStorage>
-impl MigrationStorage for &mut Box<dyn MigrationStorage> {
+impl MigrationStorage for Box<dyn MigrationStorage> {
+    #[cfg(test)]
+    fn reset(&mut self) -> Result<(), Error> {
+        (**self).reset()
+    }
+
+    fn add(&mut self, m: Migration) -> Result<(), Error> {
+        (**self).add(m)
+    }
+
+    fn remove(&mut self, m: Migration) -> Result<(), Error> {
+        (**self).remove(m)
+    }
+
+    fn lock(&mut self, timeout: Duration) -> Result<(), Error> {
+        (**self).lock(timeout)
+    }
+
+    fn unlock(&mut self) -> Result<(), Error> {
+        (**self).unlock()
+    }
+
+    fn record(&mut self, entry: HistoryEntry) -> Result<(), Error> {
+        (**self).record(entry)
+    }
+
+    fn history(&mut self) -> Result<Vec<HistoryEntry>, Error> {
+        (**self).history()
+    }
+}
+
+// Implementation of MigrationStorage for &mut T so that a storage can be lent
+// to the Engine and still be used by the caller afterwards.
+impl<T: MigrationStorage> MigrationStorage for &mut T {
     #[cfg(test)]
     fn reset(&mut self) -> Result<(), Error> {
         (**self).reset()
@@ -35,9 +83,25 @@ impl MigrationStorage for &mut Box<dyn MigrationStorage> {
     fn remove(&mut self, m: Migration) -> Result<(), Error> {
         (**self).remove(m)
     }
+
+    fn lock(&mut self, timeout: Duration) -> Result<(), Error> {
+        (**self).lock(timeout)
+    }
+
+    fn unlock(&mut self) -> Result<(), Error> {
+        (**self).unlock()
+    }
+
+    fn record(&mut self, entry: HistoryEntry) -> Result<(), Error> {
+        (**self).record(entry)
+    }
+
+    fn history(&mut self) -> Result<Vec<HistoryEntry>, Error> {
+        (**self).history()
+    }
 }
 
-impl MigrationList for &mut Box<dyn MigrationStorage> {
+impl MigrationList for Box<dyn MigrationStorage> {
     fn all<'a>(&'a mut self) -> Result<Box<(dyn Iterator<Item = Migration> + 'a)>, Error> {
         (**self).all()
     }
@@ -52,18 +116,21 @@ pub mod tests {
         migrations::FORMAT_STR, reserved, runner::Configuration as RunnerConfiguration,
         InMemoryMigrations, MySQL,
     };
-    use std::{array::IntoIter, collections::HashMap, iter::FromIterator, path::PathBuf};
+    use std::{
+        array::IntoIter,
+        collections::HashMap,
+        iter::FromIterator,
+        path::{Path, PathBuf},
+    };
 
     pub fn test_mysql_storage_configuration() -> RunnerConfiguration {
         RunnerConfiguration {
             _driver: String::from("mysql"),
-            database_number: None {},
             database: Some(String::from("mitre_test")),
-            index: None {},
             ip_or_hostname: Some(String::from("127.0.0.1")),
             password: Some(String::from("example")),
-            port: None {},
             username: Some(String::from("root")),
+            ..Default::default()
         }
     }
 
@@ -73,6 +140,127 @@ pub mod tests {
         Box::new(mysql)
     }
 
+    #[cfg(feature = "runner_postgres")]
+    pub fn test_postgresql_storage_configuration() -> RunnerConfiguration {
+        RunnerConfiguration {
+            _driver: String::from("postgres"),
+            database: Some(String::from("postgres")),
+            ip_or_hostname: Some(String::from("127.0.0.1")),
+            password: Some(String::from("example")),
+            port: Some(5432),
+            username: Some(String::from("postgres")),
+            ..Default::default()
+        }
+    }
+
+    #[cfg(feature = "runner_postgres")]
+    fn postgresql_migration_storage() -> Box<dyn MigrationStorage> {
+        let mut postgresql =
+            crate::PostgreSql::new(test_postgresql_storage_configuration()).unwrap();
+        postgresql.reset().unwrap();
+        Box::new(postgresql)
+    }
+
+    /// A storage kept in a temporary directory, which is removed (after the
+    /// storage is dropped) along with it.
+    pub struct TempDirStorage<S> {
+        storage: S,
+        _dir: tempfile::TempDir,
+    }
+
+    impl<S: MigrationStorage> TempDirStorage<S> {
+        pub fn new(make: impl FnOnce(&Path) -> Result<S, Error>) -> Result<Self, Error> {
+            let dir = tempfile::tempdir().map_err(|e| Error::ConnectionError {
+                msg: format!("Creating a temporary directory: {}", e),
+            })?;
+            Ok(TempDirStorage {
+                storage: make(dir.path())?,
+                _dir: dir,
+            })
+        }
+    }
+
+    impl<S: MigrationStorage> MigrationList for TempDirStorage<S> {
+        fn all<'a>(&'a mut self) -> Result<Box<dyn Iterator<Item = Migration> + 'a>, Error> {
+            self.storage.all()
+        }
+    }
+
+    impl<S: MigrationStorage> MigrationStorage for TempDirStorage<S> {
+        fn reset(&mut self) -> Result<(), Error> {
+            self.storage.reset()
+        }
+
+        fn add(&mut self, m: Migration) -> Result<(), Error> {
+            self.storage.add(m)
+        }
+
+        fn remove(&mut self, m: Migration) -> Result<(), Error> {
+            self.storage.remove(m)
+        }
+
+        fn lock(&mut self, timeout: Duration) -> Result<(), Error> {
+            self.storage.lock(timeout)
+        }
+
+        fn unlock(&mut self) -> Result<(), Error> {
+            self.storage.unlock()
+        }
+
+        fn record(&mut self, entry: HistoryEntry) -> Result<(), Error> {
+            self.storage.record(entry)
+        }
+
+        fn history(&mut self) -> Result<Vec<HistoryEntry>, Error> {
+            self.storage.history()
+        }
+    }
+
+    #[cfg(feature = "runner_sqlite")]
+    pub fn test_sqlite_storage_configuration(dir: &Path) -> RunnerConfiguration {
+        RunnerConfiguration {
+            _driver: String::from("sqlite"),
+            path: Some(
+                dir.join("mitre_test.sqlite3")
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[cfg(feature = "runner_sqlite")]
+    pub fn test_sqlite_storage() -> TempDirStorage<crate::Sqlite> {
+        TempDirStorage::new(|dir| crate::Sqlite::new(test_sqlite_storage_configuration(dir)))
+            .unwrap()
+    }
+
+    #[cfg(feature = "runner_sqlite")]
+    fn sqlite_migration_storage() -> Box<dyn MigrationStorage> {
+        let mut sqlite = test_sqlite_storage();
+        sqlite.reset().unwrap();
+        Box::new(sqlite)
+    }
+
+    pub fn test_file_storage_configuration(dir: &Path) -> RunnerConfiguration {
+        RunnerConfiguration {
+            _driver: String::from("file"),
+            path: Some(dir.join("mitre_test.yml").to_string_lossy().into_owned()),
+            ..Default::default()
+        }
+    }
+
+    pub fn test_file_storage() -> TempDirStorage<crate::FileStorage> {
+        TempDirStorage::new(|dir| crate::FileStorage::new(test_file_storage_configuration(dir)))
+            .unwrap()
+    }
+
+    fn file_migration_storage() -> Box<dyn MigrationStorage> {
+        let mut file = test_file_storage();
+        file.reset().unwrap();
+        Box::new(file)
+    }
+
     fn in_memory_migration_storage() -> Box<dyn MigrationStorage> {
         Box::new(InMemoryMigrations::new())
     }
@@ -108,16 +296,24 @@ pub mod tests {
         let mut impls = HashMap::<String, Box<dyn MigrationStorage>>::from_iter(IntoIter::new([
             (String::from("InMemory"), in_memory_migration_storage()),
             (String::from("MySQL"), mysql_migration_storage()),
+            (String::from("File"), file_migration_storage()),
         ]));
+        #[cfg(feature = "runner_postgres")]
+        impls.insert(String::from("PostgreSQL"), postgresql_migration_storage());
+        #[cfg(feature = "runner_sqlite")]
+        impls.insert(String::from("SQLite"), sqlite_migration_storage());
         for (name, implementation) in &mut impls {
             match lists_what_it_stores(implementation) {
                 Err(e) => return Err((name.clone(), e)),
                 _ => {}
             };
+            if let Err(e) = removes_what_it_stores(implementation) {
+                return Err((name.clone(), e));
+            }
         }
         Ok(())
     }
-    fn lists_what_it_stores(mut ms: &mut Box<dyn MigrationStorage>) -> Result<(), String> {
+    fn lists_what_it_stores(ms: &mut Box<dyn MigrationStorage>) -> Result<(), String> {
         for migration in migration_fixture() {
             match ms.add(migration) {
                 Err(e) => return Err(format!("error: {:#?}", e)),
@@ -126,4 +322,21 @@ pub mod tests {
         }
         Ok(())
     }
+    // Expects the fixture to be stored already, by lists_what_it_stores
+    fn removes_what_it_stores(ms: &mut Box<dyn MigrationStorage>) -> Result<(), String> {
+        for migration in migration_fixture() {
+            if let Err(e) = ms.remove(migration.clone()) {
+                return Err(format!("error: {:#?}", e));
+            }
+            match ms.remove(migration) {
+                Err(Error::MigrationNotFound { .. }) => {}
+                other => return Err(format!("expected MigrationNotFound, got {:#?}", other)),
+            };
+        }
+        match ms.all().map(|all| all.filter(|m| !m.built_in).count()) {
+            Ok(0) => Ok(()),
+            Ok(_) => Err(String::from("removed migrations are still listed")),
+            Err(e) => Err(format!("error: {:#?}", e)),
+        }
+    }
 }
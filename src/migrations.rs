@@ -74,6 +74,31 @@ impl Migration {
             _ => None,
         }
     }
+    /// The direction of the step which applying the migration runs, its
+    /// Change or its Up step, a migration with both, or neither, is malformed.
+    pub fn apply_direction(&self) -> Result<Direction, Error> {
+        match (
+            self.steps.contains_key(&Direction::Change),
+            self.steps.contains_key(&Direction::Up),
+        ) {
+            (true, false) => Ok(Direction::Change),
+            (false, true) => Ok(Direction::Up),
+            _ => Err(Error::MalformedMigration),
+        }
+    }
+    /// Whether the source of any step differs from that of the same step in
+    /// `other`, or the steps themselves differ. Used to notice migrations
+    /// which have been edited on disk after they were applied.
+    pub fn source_differs(&self, other: &Migration) -> bool {
+        self.steps.len() != other.steps.len()
+            || self
+                .steps
+                .iter()
+                .any(|(direction, step)| match other.steps.get(direction) {
+                    Some(other_step) => other_step.source != step.source,
+                    None => true,
+                })
+    }
 }
 
 /// Implementaion of [`PartialOrd`] for [`Migration`] to ensure that
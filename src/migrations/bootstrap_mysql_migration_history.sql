@@ -0,0 +1,32 @@
+-- Create the migration history table, an append-only log of every attempt
+-- to apply or roll back a migration, successful or not.
+CREATE TABLE IF NOT EXISTS `{{database_name}}`.`{{migration_history_table}}` (
+
+  -- Only an ordering, entries are never updated or deleted.
+  `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+
+  -- Deliberately no foreign key to `{{migrations_table}}`, failed and
+  -- rolled back migrations have no row there.
+  `version` BIGINT(14) NOT NULL,
+
+  -- Direction of the attempt
+  `direction` ENUM('up', 'down', 'change') NOT NULL,
+
+  -- Provided by Mitre in UTC, as with `stored_at` in `{{migrations_table}}`
+  `started_at` DATETIME(6) NOT NULL,
+  `finished_at` DATETIME(6) NOT NULL,
+
+  -- Name of the MigrationResult, e.g Success, Failure
+  `outcome` TINYTEXT NOT NULL,
+
+  -- The reason of a failure, NULL otherwise
+  `error` TEXT NULL,
+
+  -- Where, and by which version of Mitre, the attempt was made
+  `hostname` TINYTEXT NOT NULL,
+  `mitre_version` TINYTEXT NOT NULL,
+
+  INDEX `idx_version` (`version`)
+
+) ENGINE=InnoDB;
+-- ENGINE=InnoDB is the default, but let's be explicit.
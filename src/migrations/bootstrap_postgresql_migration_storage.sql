@@ -0,0 +1,49 @@
+-- PostgreSQL has no CREATE DATABASE IF NOT EXISTS, and a connection cannot
+-- change database, so the database named in the configuration must exist,
+-- the tables are created in its default schema.
+
+-- Create the migration storage table (migrations)
+CREATE TABLE IF NOT EXISTS {{migrations_table}} (
+
+  -- TIMESTMAP is YYYYMMDDHHMMSS just like migration filenames
+  -- assumed to be UTC, and stored as such.
+  version BIGINT NOT NULL PRIMARY KEY,
+
+  -- Not exactly a property of a migration, but metadata
+  -- stored when we store a migration in here via the MigrationStorage
+  -- trait.
+  stored_at TIMESTAMP NOT NULL,
+
+  -- Flags e.g `sorted,comma,separated,nospaces`
+  flags TEXT NOT NULL,
+
+  -- Runner Configuration Name (key in the YAML)
+  configuration_name TEXT NOT NULL,
+
+  -- Was this a built-in migration
+  built_in BOOLEAN NOT NULL
+
+);
+
+-- Create the migration storage table (steps)
+CREATE TABLE IF NOT EXISTS {{migration_steps_table}} (
+
+  -- Version must match `{{migrations_table}}`'s column of the
+  -- same name
+  version BIGINT NOT NULL REFERENCES {{migrations_table}} (version),
+
+  -- Direction, a CHECK rather than an ENUM type, types cannot be created
+  -- IF NOT EXISTS
+  direction TEXT NOT NULL CHECK (direction IN ('up', 'down', 'change')),
+
+  -- Empty string is not permitted to prevent stub migration parts being stored
+  -- by mistake
+  source TEXT NOT NULL CHECK (source <> ''),
+
+  -- The *relative* path to the migrations. Please take every care to ensure
+  -- that nothing platform|user|environment specific shows up here.
+  path TEXT NOT NULL CHECK (path <> ''),
+
+  PRIMARY KEY (version, direction)
+
+);
@@ -0,0 +1,32 @@
+-- Create the migration history table, an append-only log of every attempt
+-- to apply or roll back a migration, successful or not.
+CREATE TABLE IF NOT EXISTS {{migration_history_table}} (
+
+  -- Only an ordering, entries are never updated or deleted.
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+
+  -- Deliberately no foreign key to `{{migrations_table}}`, failed and
+  -- rolled back migrations have no row there.
+  version INTEGER NOT NULL,
+
+  -- Direction of the attempt
+  direction TEXT NOT NULL CHECK (direction IN ('up', 'down', 'change')),
+
+  -- Provided by Mitre in UTC, RFC3339
+  started_at TEXT NOT NULL,
+  finished_at TEXT NOT NULL,
+
+  -- Name of the MigrationResult, e.g Success, Failure
+  outcome TEXT NOT NULL,
+
+  -- The reason of a failure, NULL otherwise
+  error TEXT NULL,
+
+  -- Where, and by which version of Mitre, the attempt was made
+  hostname TEXT NOT NULL,
+  mitre_version TEXT NOT NULL
+
+);
+
+CREATE INDEX IF NOT EXISTS idx_{{migration_history_table}}_version
+  ON {{migration_history_table}} (version);
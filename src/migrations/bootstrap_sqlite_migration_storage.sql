@@ -0,0 +1,47 @@
+-- The database is the file at the configured `path`, SQLite creates it on
+-- first use, so there is no database to create here.
+
+-- Create the migration storage table (migrations)
+CREATE TABLE IF NOT EXISTS {{migrations_table}} (
+
+  -- TIMESTMAP is YYYYMMDDHHMMSS just like migration filenames
+  -- assumed to be UTC, and stored as such.
+  version INTEGER NOT NULL PRIMARY KEY,
+
+  -- Not exactly a property of a migration, but metadata
+  -- stored when we store a migration in here via the MigrationStorage
+  -- trait. SQLite has no date type, RFC3339 text sorts correctly.
+  stored_at TEXT NOT NULL,
+
+  -- Flags e.g `sorted,comma,separated,nospaces`
+  flags TEXT NOT NULL,
+
+  -- Runner Configuration Name (key in the YAML)
+  configuration_name TEXT NOT NULL,
+
+  -- Was this a built-in migration
+  built_in BOOLEAN NOT NULL
+
+);
+
+-- Create the migration storage table (steps)
+CREATE TABLE IF NOT EXISTS {{migration_steps_table}} (
+
+  -- Version must match `{{migrations_table}}`'s column of the
+  -- same name
+  version INTEGER NOT NULL REFERENCES {{migrations_table}} (version),
+
+  -- Direction
+  direction TEXT NOT NULL CHECK (direction IN ('up', 'down', 'change')),
+
+  -- Empty string is not permitted to prevent stub migration parts being stored
+  -- by mistake
+  source TEXT NOT NULL CHECK (source <> ''),
+
+  -- The *relative* path to the migrations. Please take every care to ensure
+  -- that nothing platform|user|environment specific shows up here.
+  path TEXT NOT NULL CHECK (path <> ''),
+
+  PRIMARY KEY (version, direction)
+
+);
@@ -1,11 +1,14 @@
 use chrono::prelude::*;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use super::{
+    driver,
     migrations::{Direction, MigrationStep},
-    Driver, DriverResult, Error, Migration, MigrationList, MigrationStorage, NamedDriver,
-    RunnerConfiguration,
+    Driver, DriverResult, Error, HistoryEntry, Migration, MigrationList, MigrationStorage,
+    MigrationTemplate, NamedDriver, RunnerConfiguration, RunnerMeta,
 };
+use indoc::indoc;
 use log::{debug, error, info, trace};
 
 use crate::migrations::FORMAT_STR;
@@ -13,6 +16,20 @@ use mysql::prelude::Queryable;
 
 const MIGRATION_STATE_TABLE_NAME: &str = "mitre_migration_state";
 const MIGRATION_STEPS_TABLE_NAME: &str = "mitre_migration_steps";
+const MIGRATION_HISTORY_TABLE_NAME: &str = "mitre_migration_history";
+
+// DATETIME(6) columns are written, and read back, in this format
+const HISTORY_DATE_TIME_FORMAT_STR: &str = "%Y-%m-%d %H:%M:%S%.6f";
+
+// Dates are read back cast to CHAR, `row` names the row they were read from
+fn parse_date_time(s: &str, row: &str) -> Result<DateTime<Utc>, Error> {
+    NaiveDateTime::parse_from_str(s, HISTORY_DATE_TIME_FORMAT_STR)
+        .map(|date_time| Utc.from_utc_datetime(&date_time))
+        .map_err(|e| Error::QueryFailed {
+            reason: None {},
+            msg: format!("{} has an invalid date {:?}: {}", row, s, e),
+        })
+}
 
 pub struct MySQL {
     conn: mysql::Conn,
@@ -57,6 +74,14 @@ impl MySQL {
         &mut self.conn
     }
 
+    // One lock per database, as that is where the migration state lives.
+    fn lock_name(&self) -> Result<String, Error> {
+        match &self.config.database {
+            Some(database) => Ok(format!("mitre_migration_lock.{}", database)),
+            None => Err(Error::ConfigurationIncomplete),
+        }
+    }
+
     fn bootstrap_migrations(&self) -> Vec<Migration> {
         log::trace!("getting bootstrap migrations");
         vec![Migration {
@@ -85,6 +110,31 @@ impl MySQL {
                 ),
             ])
             .collect(),
+        },
+        Migration {
+            date_time: chrono::Utc::now().naive_utc(),
+            built_in: true,
+            flags: vec![],
+            configuration_name: String::from("mitre"),
+            steps: std::array::IntoIter::new([
+                (
+                    Direction::Up,
+                    MigrationStep {
+                        path: PathBuf::from("built/in/migration"),
+                        source: String::from(include_str!(
+                            "migrations/bootstrap_mysql_migration_history.sql"
+                        )),
+                    },
+                ),
+                (
+                    Direction::Down,
+                    MigrationStep {
+                        path: PathBuf::from("built/in/migration"),
+                        source: String::from("DROP TABLE IF EXISTS `{{database_name}}`.`{{migration_history_table}}`;"),
+                    },
+                ),
+            ])
+            .collect(),
         }]
     }
 
@@ -102,24 +152,18 @@ impl MySQL {
             .insert_str("database_name", database)
             .insert_str("migrations_table", MIGRATION_STATE_TABLE_NAME)
             .insert_str("migration_steps_table", MIGRATION_STEPS_TABLE_NAME)
+            .insert_str("migration_history_table", MIGRATION_HISTORY_TABLE_NAME)
             .build())
     }
 
+    fn render_statements(&self, query: &str) -> Result<String, Error> {
+        driver::render_template(query, &self.template_ctx()?, "queries")
+    }
+
     // Statements does not imply _prepared_ statements
     // in the name because a &str may contain multiple expressions
     fn apply_statements(&mut self, query: &str) -> Result<(), Error> {
-        let q = match mustache::compile_str(query)
-            .unwrap()
-            .render_data_to_string(&self.template_ctx()?)
-        {
-            Ok(q) => q,
-            Err(_e) => {
-                return Err(Error::QueryFailed {
-                    reason: None {},
-                    msg: String::from("couldn't render Mustache template of queries"),
-                })
-            }
-        };
+        let q = self.render_statements(query)?;
 
         let result = match self.conn().query_iter(q.clone()) {
             Ok(mut result) => {
@@ -165,36 +209,39 @@ impl MySQL {
         }
         Ok(())
     }
+}
 
-    fn apply(&mut self, m: &Migration) -> Result<DriverResult, Error> {
-        let change = m.steps.get(&Direction::Change);
-        let up = m.steps.get(&Direction::Up);
-        let s = match (change, up) {
-            (Some(_), Some(_)) => return Err(Error::MalformedMigration),
-            (None, None) => return Err(Error::MalformedMigration),
-            (None, Some(up)) => up,
-            (Some(change), None) => change,
-        };
-        self.apply_statements(&s.source)?;
+impl Driver for MySQL {
+    fn run(&mut self, ms: &MigrationStep) -> Result<DriverResult, Error> {
+        self.apply_statements(&ms.source)?;
         Ok(DriverResult::Success)
     }
 
-    fn unapply(&mut self, m: &Migration) -> Result<DriverResult, Error> {
-        let s = match m.steps.get(&Direction::Down) {
-            Some(down) => down,
-            None => return Ok(DriverResult::NothingToDo),
-        };
-        self.apply_statements(&s.source)?;
-        Ok(DriverResult::Success)
+    fn render(&self, ms: &MigrationStep) -> Result<String, Error> {
+        self.render_statements(&ms.source)
     }
-}
 
-impl Driver for MySQL {
-    fn apply(&mut self, m: &Migration) -> Result<DriverResult, Error> {
-        self.apply(m)
+    fn migration_template(&self) -> MigrationTemplate {
+        MigrationTemplate {
+            extension: "sql",
+            up: indoc!(
+                "
+                -- Put your migration here
+                CREATE TABLE your_table (
+                    column_one VARCHAR(255) NOT NULL
+                );
+                "
+            ),
+            down: Some(indoc!(
+                "
+                DROP TABLE your_table;
+                "
+            )),
+        }
     }
-    fn unapply(&mut self, m: &Migration) -> Result<DriverResult, Error> {
-        self.unapply(m)
+
+    fn meta(&self) -> RunnerMeta<'static> {
+        crate::reserved::runner_by_name(crate::reserved::MYSQL).expect("reserved word not found")
     }
 }
 
@@ -415,8 +462,184 @@ impl MigrationStorage for MySQL {
         }
     }
 
-    fn remove(&mut self, _: Migration) -> Result<(), Error> {
-        todo!();
+    fn remove(&mut self, m: Migration) -> Result<(), Error> {
+        trace!("remove about to bootstrap");
+        self.bootstrap()?;
+
+        // Note, that transaction will be rolled back implicitly on Drop, if not committed.
+        let mut tx = match self.conn().start_transaction(::mysql::TxOpts::default()) {
+            Ok(tr) => Ok(tr),
+            Err(e) => Err(Error::QueryFailed {
+                reason: Some(e),
+                msg: String::from("could not start transaction"),
+            }),
+        }?;
+        // Steps first, fk_migration_version references the migration
+        match tx.exec_drop(
+            format!(
+                "DELETE FROM {} WHERE `version` = ?;",
+                MIGRATION_STEPS_TABLE_NAME
+            ),
+            (m.version(),),
+        ) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::QueryFailed {
+                reason: Some(e),
+                msg: String::from("could not remove migration steps from migration steps table"),
+            }),
+        }?;
+        match tx.exec_drop(
+            format!(
+                "DELETE FROM {} WHERE `version` = ?;",
+                MIGRATION_STATE_TABLE_NAME
+            ),
+            (m.version(),),
+        ) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::QueryFailed {
+                reason: Some(e),
+                msg: String::from("could not remove migration from migration state table"),
+            }),
+        }?;
+        if tx.affected_rows() == 0 {
+            return Err(Error::MigrationNotFound {
+                version: m.version(),
+            });
+        }
+        match tx.commit() {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::QueryFailed {
+                reason: Some(e),
+                msg: String::from("could not commit transaction"),
+            }),
+        }
+    }
+
+    // GET_LOCK locks are server-wide and held by the connection, so they
+    // exclude other mitre processes using the same server, and are released
+    // by the server should this process die whilst holding one.
+    fn lock(&mut self, timeout: Duration) -> Result<(), Error> {
+        let name = self.lock_name()?;
+        match self
+            .conn()
+            .exec_first::<Option<i64>, _, _>("SELECT GET_LOCK(?, ?)", (name, timeout.as_secs()))
+        {
+            Ok(Some(Some(1))) => Ok(()),
+            Ok(Some(Some(0))) => Err(Error::LockTimeout { timeout }),
+            Ok(_) => Err(Error::QueryFailed {
+                reason: None {},
+                msg: String::from("GET_LOCK returned an error (NULL)"),
+            }),
+            Err(e) => Err(Error::QueryFailed {
+                reason: Some(e),
+                msg: String::from("could not acquire migration lock"),
+            }),
+        }
+    }
+
+    fn unlock(&mut self) -> Result<(), Error> {
+        let name = self.lock_name()?;
+        match self.conn().exec_drop("SELECT RELEASE_LOCK(?)", (name,)) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::QueryFailed {
+                reason: Some(e),
+                msg: String::from("could not release migration lock"),
+            }),
+        }
+    }
+
+    fn record(&mut self, entry: HistoryEntry) -> Result<(), Error> {
+        // A failed attempt may well be the first time this storage is used
+        self.bootstrap()?;
+        let q = indoc::formatdoc!(
+            "
+          INSERT INTO {}
+          (
+            `version`,
+            `direction`,
+            `started_at`,
+            `finished_at`,
+            `outcome`,
+            `error`,
+            `hostname`,
+            `mitre_version`
+          )
+          VALUES ( ?, ?, ?, ?, ?, ?, ?, ? );",
+            MIGRATION_HISTORY_TABLE_NAME
+        );
+        match self.conn().exec_drop(
+            q,
+            (
+                entry.version,
+                format!("{:?}", entry.direction).to_lowercase(),
+                entry
+                    .started_at
+                    .format(HISTORY_DATE_TIME_FORMAT_STR)
+                    .to_string(),
+                entry
+                    .finished_at
+                    .format(HISTORY_DATE_TIME_FORMAT_STR)
+                    .to_string(),
+                entry.outcome,
+                entry.error,
+                entry.hostname,
+                entry.mitre_version,
+            ),
+        ) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::QueryFailed {
+                reason: Some(e),
+                msg: String::from("could not insert into migration history table"),
+            }),
+        }
+    }
+
+    fn history(&mut self) -> Result<Vec<HistoryEntry>, Error> {
+        self.bootstrap()?;
+        let q = format!(
+            "SELECT `version`, `direction`, CAST(`started_at` AS CHAR), CAST(`finished_at` AS CHAR), `outcome`, `error`, `hostname`, `mitre_version` FROM {t} ORDER BY `id` ASC",
+            t = MIGRATION_HISTORY_TABLE_NAME
+        );
+        match self.conn().query_map(
+            q,
+            |(
+                version,
+                direction,
+                started_at,
+                finished_at,
+                outcome,
+                error,
+                hostname,
+                mitre_version,
+            ): (
+                String,
+                String,
+                String,
+                String,
+                String,
+                Option<String>,
+                String,
+                String,
+            )| {
+                let entry = format!("migration history entry of {} ({})", version, direction);
+                Ok(HistoryEntry {
+                    direction: Direction::from(direction),
+                    started_at: parse_date_time(&started_at, &entry)?,
+                    finished_at: parse_date_time(&finished_at, &entry)?,
+                    version,
+                    outcome,
+                    error,
+                    hostname,
+                    mitre_version,
+                })
+            },
+        ) {
+            Ok(history) => history.into_iter().collect(),
+            Err(e) => Err(Error::QueryFailed {
+                reason: Some(e),
+                msg: String::from("Querying the MySQL migration history"),
+            }),
+        }
     }
 }
 
@@ -425,3 +648,46 @@ impl NamedDriver for MySQL {
         "mysql"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migration_storage::tests::test_mysql_storage_configuration;
+
+    #[test]
+    fn history_names_entries_with_malformed_dates() -> Result<(), String> {
+        let mut mysql =
+            MySQL::new(test_mysql_storage_configuration()).map_err(|e| format!("{:?}", e))?;
+        mysql.reset().map_err(|e| format!("{:?}", e))?;
+        mysql
+            .record(HistoryEntry {
+                version: String::from("20210101120000"),
+                direction: Direction::Up,
+                started_at: Utc::now(),
+                finished_at: Utc::now(),
+                outcome: String::from("success"),
+                error: None {},
+                hostname: String::from("localhost"),
+                mitre_version: String::from("0.0.0"),
+            })
+            .map_err(|e| format!("{:?}", e))?;
+        // DATETIME columns won't hold a malformed date, so make room for one
+        for q in &[
+            "ALTER TABLE {t} MODIFY `started_at` VARCHAR(32)",
+            "UPDATE {t} SET `started_at` = 'yesterday'",
+        ] {
+            mysql
+                .conn()
+                .query_drop(q.replace("{t}", MIGRATION_HISTORY_TABLE_NAME))
+                .map_err(|e| e.to_string())?;
+        }
+        match mysql.history() {
+            Err(Error::QueryFailed { msg, .. }) => {
+                assert!(msg.contains("20210101120000 (up)"), "{}", msg);
+                Ok(())
+            }
+            Err(e) => Err(format!("unexpected error {:?}", e)),
+            Ok(_) => Err(String::from("read back a malformed date")),
+        }
+    }
+}
@@ -0,0 +1,609 @@
+//! Drivers which run out of process, as executables named `mitre-driver-*`
+//! on the `PATH`, for backends which aren't written in Rust, or aren't
+//! compiled into mitre. A `_driver` which isn't registered (see
+//! [`crate::register_driver`]) is looked up among them.
+//!
+//! Mitre writes one JSON-RPC 2.0 request per line to the plugin's stdin,
+//! and reads one response per line from its stdout, the plugin's stderr is
+//! left to the user. Plugins should exit when their stdin is closed, and are
+//! killed if they don't, or if they don't answer in time: 30 seconds, or for
+//! `apply` and `unapply` the configuration's `timeout` (an hour by default.)
+//!
+//! - `describe`, with `{"protocol_version": 1}`, answered with the protocol
+//!   version the plugin speaks, its `name` (the executable's name, without
+//!   `mitre-driver-`, in any case), a `description`, the `extensions` of its
+//!   migrations, and the `template` for new ones:
+//!   `{"protocol_version": 1, "name": "MongoDB", "description": "..",
+//!   "extensions": ["js"], "template": {"extension": "js", "up": "..",
+//!   "down": null}}`
+//! - `handshake`, with `{"protocol_version": 1}`, answered with
+//!   `{"protocol_version": 1}`, when a plugin which was described already is
+//!   started again to run migrations.
+//! - `health_check`, with `{"config": {..}}`, answered with any result when
+//!   the backend can be reached with that configuration.
+//! - `apply` and `unapply`, with `{"config": {..}, "step": {"path": "..",
+//!   "source": ".."}}`, answered with `{"status": "success"}` or
+//!   `{"status": "nothing_to_do"}`. `apply` is given the up (or change) step,
+//!   `unapply` the down step.
+//!
+//! Errors are JSON-RPC errors, `{"code": 1, "message": ".."}`. The config is
+//! the driver's configuration, with null for the values which aren't set.
+//! Plugins are given the step's source as it is on disk, rendering any
+//! templates in it is up to them.
+
+use super::{
+    migrations::MigrationStep, Direction, Driver, DriverResult, Error, Migration,
+    MigrationTemplate, NamedDriver, RunnerConfiguration, RunnerMeta,
+};
+use lazy_static::lazy_static;
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The version of the protocol spoken with plugins, bumped when requests or
+/// responses change in a way older plugins (or older mitres) can't handle.
+pub const PROTOCOL_VERSION: u64 = 1;
+
+const EXECUTABLE_PREFIX: &str = "mitre-driver-";
+
+// How long plugins have to answer anything other than a step, and to exit
+// once their stdin is closed, before they're killed.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(30);
+const EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How long plugins have to apply or unapply a step, unless the configuration
+// has a `timeout`.
+const DEFAULT_STEP_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+lazy_static! {
+    // Plugins are looked up, and described, once per name and process.
+    static ref FOUND: Mutex<HashMap<String, Option<Executable>>> = Mutex::new(HashMap::new());
+}
+
+/// A plugin found on the `PATH`, with what it told us about itself.
+#[derive(Debug, Clone)]
+pub(crate) struct Executable {
+    path: PathBuf,
+    meta: RunnerMeta<'static>,
+    template: MigrationTemplate,
+}
+
+/// The [`RunnerMeta`] of every plugin on the `PATH`, each of them is started
+/// to describe itself, so this is for listing them only.
+pub(crate) fn runners() -> Vec<RunnerMeta<'static>> {
+    let search_path = std::env::var_os("PATH").unwrap_or_default();
+    let mut names: Vec<String> = vec![];
+    for path in candidates(&search_path) {
+        if let Some(name) = plugin_name(&path) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+        .iter()
+        .filter_map(|name| find(name))
+        .map(|e| e.meta)
+        .collect()
+}
+
+/// The plugin named `name`, if there's one on the `PATH`. Only that plugin
+/// is started, to describe itself.
+pub(crate) fn find(name: &str) -> Option<Executable> {
+    let name = name.to_lowercase();
+    let mut found = FOUND.lock().unwrap_or_else(|e| e.into_inner());
+    found
+        .entry(name.clone())
+        .or_insert_with(|| locate(&std::env::var_os("PATH").unwrap_or_default(), &name))
+        .clone()
+}
+
+/// The [`RunnerMeta`] of the plugin named `name`, see [`find`].
+pub(crate) fn runner_meta(name: &str) -> Option<RunnerMeta<'static>> {
+    find(name).map(|e| e.meta)
+}
+
+// Descriptions live as long as the process, as the reserved words do.
+fn leak(s: &str) -> &'static str {
+    Box::leak(String::from(s).into_boxed_str())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    match std::fs::metadata(path) {
+        Ok(m) => m.is_file() && m.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+// The lowercase name of the plugin at `path`, if it is one.
+fn plugin_name(path: &Path) -> Option<String> {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|n| n.strip_prefix(EXECUTABLE_PREFIX))
+        .map(|n| n.to_lowercase())
+}
+
+// Every executable which may be a plugin, in the order of the search path.
+fn candidates(search_path: &OsStr) -> Vec<PathBuf> {
+    let mut found = vec![];
+    for dir in std::env::split_paths(search_path) {
+        let mut paths: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+            Err(_) => continue,
+        };
+        paths.sort();
+        found.extend(
+            paths
+                .into_iter()
+                .filter(|p| plugin_name(p).is_some() && is_executable(p)),
+        );
+    }
+    found
+}
+
+fn locate(search_path: &OsStr, name: &str) -> Option<Executable> {
+    // The first on the search path wins, as it would in a shell
+    let path = candidates(search_path)
+        .into_iter()
+        .find(|p| plugin_name(p).as_deref() == Some(name))?;
+    match Connection::spawn(&path).and_then(|mut c| c.describe()) {
+        Ok((meta, _)) if meta.name.to_lowercase() != name => {
+            warn!(
+                "ignoring plugin {}, it describes itself as {}",
+                path.display(),
+                meta.name
+            );
+            None {}
+        }
+        Ok((meta, template)) => {
+            debug!("found plugin {} at {}", meta.name, path.display());
+            Some(Executable {
+                path,
+                meta,
+                template,
+            })
+        }
+        Err(e) => {
+            warn!("ignoring plugin {}: {:?}", path.display(), e);
+            None {}
+        }
+    }
+}
+
+// A running plugin, which answers requests one at a time.
+struct Connection {
+    path: PathBuf,
+    child: Child,
+    stdin: Option<ChildStdin>,
+    answers: Receiver<String>,
+    next_id: u64,
+}
+
+impl Connection {
+    fn spawn(path: &Path) -> Result<Self, Error> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(Error::Io)?;
+        let stdin = child.stdin.take();
+        let stdout = match child.stdout.take() {
+            Some(stdout) => stdout,
+            None => {
+                return Err(Error::ConnectionError {
+                    msg: format!("no stdout for plugin {}", path.display()),
+                })
+            }
+        };
+        // Answers are read on a thread of their own, so that we can stop
+        // waiting for them.
+        let (sender, answers) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut stdout = BufReader::new(stdout);
+            loop {
+                let mut line = String::new();
+                match stdout.read_line(&mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        Ok(Connection {
+            path: path.to_path_buf(),
+            child,
+            stdin,
+            answers,
+            next_id: 1,
+        })
+    }
+
+    fn protocol_error(&self, msg: &str) -> Error {
+        Error::ConnectionError {
+            msg: format!("plugin {}: {}", self.path.display(), msg),
+        }
+    }
+
+    // The outer error is for when the plugin couldn't be talked to, or
+    // didn't answer within the timeout (and was killed), the inner one is the
+    // message of an error the plugin answered with.
+    fn call(
+        &mut self,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<Result<Value, String>, Error> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        debug!("to {}: {}", self.path.display(), request);
+        match self.stdin.as_mut() {
+            Some(stdin) => writeln!(stdin, "{}", request).map_err(Error::Io)?,
+            None => return Err(self.protocol_error("stdin is closed")),
+        }
+
+        let line = match self.answers.recv_timeout(timeout) {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Timeout) => {
+                warn!("killing {} after {:?}", self.path.display(), timeout);
+                let _ = self.child.kill();
+                return Err(
+                    self.protocol_error(&format!("did not answer {} within {:?}", method, timeout))
+                );
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(self.protocol_error(&format!("exited instead of answering {}", method)))
+            }
+        };
+        debug!("from {}: {}", self.path.display(), line.trim_end());
+        let response: Value = serde_json::from_str(&line)
+            .map_err(|e| self.protocol_error(&format!("answered {} with {}", method, e)))?;
+        if response["id"] != json!(id) {
+            return Err(self.protocol_error(&format!(
+                "answered request {} with the id {}",
+                id, response["id"]
+            )));
+        }
+        match (response.get("result"), response.get("error")) {
+            (_, Some(error)) => Ok(Err(error["message"]
+                .as_str()
+                .map(String::from)
+                .unwrap_or_else(|| error.to_string()))),
+            (Some(result), None) => Ok(Ok(result.clone())),
+            (None, None) => Err(self.protocol_error(&format!(
+                "answered {} with neither a result nor an error",
+                method
+            ))),
+        }
+    }
+
+    // Calls a method which answers with the version of the protocol the
+    // plugin speaks, which must be ours.
+    fn versioned_call(&mut self, method: &str) -> Result<Value, Error> {
+        let result = self
+            .call(
+                method,
+                json!({ "protocol_version": PROTOCOL_VERSION }),
+                ANSWER_TIMEOUT,
+            )?
+            .map_err(|msg| self.protocol_error(&msg))?;
+        if result["protocol_version"].as_u64() != Some(PROTOCOL_VERSION) {
+            return Err(self.protocol_error(&format!(
+                "speaks protocol version {}, not {}",
+                result["protocol_version"], PROTOCOL_VERSION
+            )));
+        }
+        Ok(result)
+    }
+
+    fn describe(&mut self) -> Result<(RunnerMeta<'static>, MigrationTemplate), Error> {
+        let d = self.versioned_call("describe")?;
+        let text = |v: &Value| v.as_str().map(leak);
+        let (name, extension, up) = match (
+            text(&d["name"]),
+            text(&d["template"]["extension"]),
+            text(&d["template"]["up"]),
+        ) {
+            (Some(name), Some(extension), Some(up)) => (name, extension, up),
+            _ => return Err(self.protocol_error("description is incomplete")),
+        };
+        let meta = RunnerMeta {
+            name,
+            desc: text(&d["description"]).unwrap_or(""),
+            exts: match d["extensions"].as_array() {
+                Some(exts) => exts.iter().filter_map(text).collect(),
+                None => vec![extension],
+            },
+        };
+        let template = MigrationTemplate {
+            extension,
+            up,
+            down: text(&d["template"]["down"]),
+        };
+        Ok((meta, template))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // Closing stdin asks the plugin to exit
+        self.stdin.take();
+        let started = Instant::now();
+        while let Ok(None) = self.child.try_wait() {
+            if started.elapsed() >= EXIT_TIMEOUT {
+                warn!("killing {}, it did not exit", self.path.display());
+                let _ = self.child.kill();
+                break;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        let _ = self.child.wait();
+    }
+}
+
+/// A driver which runs migrations with a plugin, see the [module
+/// documentation](self) for the protocol.
+pub struct Plugin {
+    config: RunnerConfiguration,
+    executable: Executable,
+    connection: Connection,
+}
+
+impl Plugin {
+    pub(crate) fn new(executable: Executable, config: RunnerConfiguration) -> Result<Self, Error> {
+        let mut connection = Connection::spawn(&executable.path)?;
+        // It was described when it was found, but may have been replaced
+        // with one speaking another version since.
+        connection.versioned_call("handshake")?;
+        if let Err(msg) = connection.call(
+            "health_check",
+            json!({ "config": config.to_json() }),
+            ANSWER_TIMEOUT,
+        )? {
+            return Err(Error::ConnectionError { msg });
+        }
+        Ok(Plugin {
+            config,
+            executable,
+            connection,
+        })
+    }
+
+    fn step(&mut self, method: &str, ms: &MigrationStep) -> Result<DriverResult, Error> {
+        info!(
+            "{} {} with {}",
+            method,
+            ms.path.display(),
+            self.executable.path.display()
+        );
+        let params = json!({
+            "config": self.config.to_json(),
+            "step": {"path": ms.path.to_string_lossy(), "source": ms.source},
+        });
+        let timeout = self
+            .config
+            .timeout
+            .map(|s| Duration::from_secs(s.into()))
+            .unwrap_or(DEFAULT_STEP_TIMEOUT);
+        match self.connection.call(method, params, timeout)? {
+            Err(msg) => Err(Error::QueryFailed {
+                reason: None {},
+                msg,
+            }),
+            Ok(result) => match result["status"].as_str() {
+                Some("success") => Ok(DriverResult::Success),
+                Some("nothing_to_do") => Ok(DriverResult::NothingToDo),
+                _ => Err(self.connection.protocol_error(&format!(
+                    "answered {} with the status {}",
+                    method, result["status"]
+                ))),
+            },
+        }
+    }
+}
+
+impl Driver for Plugin {
+    fn run(&mut self, ms: &MigrationStep) -> Result<DriverResult, Error> {
+        self.step("apply", ms)
+    }
+
+    fn unapply(&mut self, m: &Migration) -> Result<DriverResult, Error> {
+        match m.steps.get(&Direction::Down) {
+            Some(down) => self.step("unapply", down),
+            None => Ok(DriverResult::NothingToDo),
+        }
+    }
+
+    fn render(&self, ms: &MigrationStep) -> Result<String, Error> {
+        Ok(ms.source.clone())
+    }
+
+    fn migration_template(&self) -> MigrationTemplate {
+        self.executable.template.clone()
+    }
+
+    fn meta(&self) -> RunnerMeta<'static> {
+        self.executable.meta.clone()
+    }
+}
+
+impl NamedDriver for Plugin {
+    fn name() -> &'static str {
+        "plugin"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A plugin which logs the requests it gets in requests.log, and can't
+    // unapply anything.
+    fn stub_plugin(dir: &Path) -> Result<(), String> {
+        let path = dir.join("mitre-driver-stub");
+        std::fs::write(
+            &path,
+            indoc::indoc! {r#"
+                #!/bin/sh
+                while read -r line; do
+                  printf '%s\n' "$line" >> "$(dirname "$0")/requests.log"
+                  id=$(printf '%s' "$line" | sed 's/.*"id":\([0-9]*\).*/\1/')
+                  case "$line" in
+                    *'"method":"describe"'*)
+                      result='{"protocol_version":1,"name":"Stub","description":"For the tests","extensions":["stub"],"template":{"extension":"stub","up":"up\n","down":null}}' ;;
+                    *'"method":"handshake"'*) result='{"protocol_version":1}' ;;
+                    *'"method":"unapply"'*)
+                      printf '%s\n' '{"jsonrpc":"2.0","id":'"$id"',"error":{"code":1,"message":"stubs are forever"}}'
+                      continue ;;
+                    *'"method":"apply"'*) result='{"status":"success"}' ;;
+                    *) result='{}' ;;
+                  esac
+                  printf '%s\n' '{"jsonrpc":"2.0","id":'"$id"',"result":'"$result"'}'
+                done
+            "#},
+        )
+        .map_err(|e| e.to_string())?;
+        // Not executable, so not a plugin
+        std::fs::write(dir.join("mitre-driver-readme"), "").map_err(|e| e.to_string())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn config() -> RunnerConfiguration {
+        RunnerConfiguration {
+            _driver: String::from("stub"),
+            database: Some(String::from("stubs")),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn kills_plugins_which_do_not_answer_in_time() -> Result<(), String> {
+        let dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+        let path = dir.path().join("mitre-driver-mute");
+        std::fs::write(&path, "#!/bin/sh\nexec sleep 5\n").map_err(|e| e.to_string())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+                .map_err(|e| e.to_string())?;
+        }
+        let started = Instant::now();
+        let mut connection = Connection::spawn(&path).map_err(|e| format!("{:?}", e))?;
+        match connection.call("describe", json!({}), Duration::from_millis(100)) {
+            Err(Error::ConnectionError { msg }) => assert!(msg.contains("did not answer")),
+            Err(e) => return Err(format!("unexpected error {:?}", e)),
+            Ok(_) => return Err(String::from("the plugin answered")),
+        }
+        drop(connection);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        Ok(())
+    }
+
+    #[test]
+    fn finds_plugins_by_name_and_their_description() -> Result<(), String> {
+        let dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+        stub_plugin(dir.path())?;
+        assert!(locate(dir.path().as_os_str(), "readme").is_none());
+        let found = locate(dir.path().as_os_str(), "stub").ok_or("the stub was not found")?;
+        assert_eq!(
+            found.meta,
+            RunnerMeta {
+                name: "Stub",
+                desc: "For the tests",
+                exts: vec!["stub"],
+            }
+        );
+        assert_eq!(
+            found.template,
+            MigrationTemplate {
+                extension: "stub",
+                up: "up\n",
+                down: None {},
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn applies_and_unapplies_steps_with_the_plugin() -> Result<(), String> {
+        let dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+        stub_plugin(dir.path())?;
+        let executable = locate(dir.path().as_os_str(), "stub").ok_or("the stub was not found")?;
+        let mut plugin = Plugin::new(executable, config()).map_err(|e| format!("{:?}", e))?;
+
+        let mut steps = HashMap::new();
+        for (direction, file) in
+            std::array::IntoIter::new([(Direction::Up, "up.stub"), (Direction::Down, "down.stub")])
+        {
+            steps.insert(
+                direction,
+                MigrationStep {
+                    path: PathBuf::from("/foo/20210503213400_stubs.stub").join(file),
+                    source: format!("\"{}\"", file),
+                },
+            );
+        }
+        let m = Migration {
+            date_time: chrono::Utc::now().naive_utc(),
+            steps,
+            built_in: false,
+            flags: vec![],
+            configuration_name: String::from("stubs"),
+        };
+        match plugin.apply(&m) {
+            Ok(DriverResult::Success) => {}
+            Ok(_) => return Err(String::from("expected the step to be applied")),
+            Err(e) => return Err(format!("{:?}", e)),
+        }
+        match plugin.unapply(&m) {
+            Err(Error::QueryFailed { msg, .. }) => assert_eq!(msg, "stubs are forever"),
+            Err(e) => return Err(format!("unexpected error {:?}", e)),
+            Ok(_) => return Err(String::from("the plugin's error was not reported")),
+        }
+        drop(plugin);
+
+        let log =
+            std::fs::read_to_string(dir.path().join("requests.log")).map_err(|e| e.to_string())?;
+        let methods: Vec<Value> = log
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).map(|r| r["method"].clone()))
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?;
+        // Described once when found, the second start only checks the version
+        assert_eq!(
+            methods,
+            vec!["describe", "handshake", "health_check", "apply", "unapply"]
+        );
+        let apply: Value = serde_json::from_str(log.lines().nth(3).unwrap_or_default())
+            .map_err(|e| e.to_string())?;
+        assert_eq!(apply["params"]["config"]["database"], "stubs");
+        assert_eq!(apply["params"]["step"]["source"], "\"up.stub\"");
+        Ok(())
+    }
+}
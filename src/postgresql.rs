@@ -0,0 +1,574 @@
+use chrono::prelude::*;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::{
+    driver,
+    migrations::{Direction, MigrationStep},
+    Driver, DriverResult, Error, HistoryEntry, Migration, MigrationList, MigrationStorage,
+    MigrationTemplate, NamedDriver, RunnerConfiguration, RunnerMeta,
+};
+use indoc::indoc;
+use log::{debug, info, trace};
+
+use crate::migrations::FORMAT_STR;
+
+const MIGRATION_STATE_TABLE_NAME: &str = "mitre_migration_state";
+const MIGRATION_STEPS_TABLE_NAME: &str = "mitre_migration_steps";
+const MIGRATION_HISTORY_TABLE_NAME: &str = "mitre_migration_history";
+
+// TIMESTAMP columns are written, and read back (with to_char), in this format
+const DATE_TIME_FORMAT_STR: &str = "%Y-%m-%d %H:%M:%S%.6f";
+const TO_CHAR_FORMAT_STR: &str = "YYYY-MM-DD HH24:MI:SS.US";
+
+// How often to retry taking the advisory lock whilst waiting for it
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Runs `.sql` migrations, each step in a transaction of its own, and can
+/// store Mitre's state.
+pub struct PostgreSql {
+    client: postgres::Client,
+    config: RunnerConfiguration,
+}
+
+// The postgres crate's errors cannot be carried in Error::QueryFailed, which
+// is tied to the mysql crate, so keep their description in the message.
+fn query_failed(e: postgres::Error, msg: &str) -> Error {
+    Error::QueryFailed {
+        reason: None {},
+        msg: format!("{}: {}", msg, e),
+    }
+}
+
+// Versions are stored as a BIGINT, just as they are in MySQL
+fn version_number(version: &str) -> Result<i64, Error> {
+    version.parse::<i64>().map_err(|e| Error::QueryFailed {
+        reason: None {},
+        msg: format!("version {:?} is not a number: {}", version, e),
+    })
+}
+
+// The version of a stored migration is its timestamp
+fn version_date_time(version: i64) -> Result<NaiveDateTime, Error> {
+    NaiveDateTime::parse_from_str(&version.to_string(), FORMAT_STR).map_err(|e| {
+        Error::QueryFailed {
+            reason: None {},
+            msg: format!("stored migration {} has an invalid version: {}", version, e),
+        }
+    })
+}
+
+// Dates are read back with to_char, `row` names the row they were read from
+fn parse_date_time(s: &str, row: &str) -> Result<DateTime<Utc>, Error> {
+    NaiveDateTime::parse_from_str(s, DATE_TIME_FORMAT_STR)
+        .map(|date_time| Utc.from_utc_datetime(&date_time))
+        .map_err(|e| Error::QueryFailed {
+            reason: None {},
+            msg: format!("{} has an invalid date {:?}: {}", row, s, e),
+        })
+}
+
+impl PostgreSql {
+    pub fn new(config: RunnerConfiguration) -> Result<Self, Error> {
+        let database = match &config.database {
+            Some(database) => database.clone(),
+            None => return Err(Error::ConfigurationIncomplete),
+        };
+        let mut c = postgres::Config::new();
+        c.dbname(&database);
+        if let Some(username) = &config.username {
+            c.user(username);
+        }
+        if let Some(password) = &config.password {
+            c.password(password);
+        }
+        if let Some(ip_or_hostname) = &config.ip_or_hostname {
+            c.host(ip_or_hostname);
+        }
+        if let Some(port) = config.port {
+            c.port(port);
+        }
+        info!("connecting to postgres database {:?}", database);
+        Ok(PostgreSql {
+            client: match c.connect(postgres::NoTls) {
+                Ok(client) => client,
+                Err(e) => {
+                    return Err(Error::ConnectionError {
+                        msg: format!("Connecting to PostgreSQL: {}", e),
+                    })
+                }
+            },
+            config,
+        })
+    }
+
+    fn bootstrap_migrations(&self) -> Vec<Migration> {
+        log::trace!("getting bootstrap migrations");
+        vec![
+            Migration {
+                date_time: chrono::Utc::now().naive_utc(),
+                built_in: true,
+                flags: vec![],
+                configuration_name: String::from("mitre"),
+                steps: std::array::IntoIter::new([
+                    (
+                        Direction::Up,
+                        MigrationStep {
+                            path: PathBuf::from("built/in/migration"),
+                            source: String::from(include_str!(
+                                "migrations/bootstrap_postgresql_migration_storage.sql"
+                            )),
+                        },
+                    ),
+                    (
+                        Direction::Down,
+                        MigrationStep {
+                            path: PathBuf::from("built/in/migration"),
+                            source: String::from(
+                                "DROP TABLE IF EXISTS {{migration_steps_table}}, {{migrations_table}};",
+                            ),
+                        },
+                    ),
+                ])
+                .collect(),
+            },
+            Migration {
+                date_time: chrono::Utc::now().naive_utc(),
+                built_in: true,
+                flags: vec![],
+                configuration_name: String::from("mitre"),
+                steps: std::array::IntoIter::new([
+                    (
+                        Direction::Up,
+                        MigrationStep {
+                            path: PathBuf::from("built/in/migration"),
+                            source: String::from(include_str!(
+                                "migrations/bootstrap_postgresql_migration_history.sql"
+                            )),
+                        },
+                    ),
+                    (
+                        Direction::Down,
+                        MigrationStep {
+                            path: PathBuf::from("built/in/migration"),
+                            source: String::from(
+                                "DROP TABLE IF EXISTS {{migration_history_table}};",
+                            ),
+                        },
+                    ),
+                ])
+                .collect(),
+            },
+        ]
+    }
+
+    fn template_ctx(&self) -> mustache::Data {
+        mustache::MapBuilder::new()
+            .insert_str(
+                "database_name",
+                self.config.database.clone().unwrap_or_default(),
+            )
+            .insert_str("migrations_table", MIGRATION_STATE_TABLE_NAME)
+            .insert_str("migration_steps_table", MIGRATION_STEPS_TABLE_NAME)
+            .insert_str("migration_history_table", MIGRATION_HISTORY_TABLE_NAME)
+            .build()
+    }
+
+    fn render_statements(&self, query: &str) -> Result<String, Error> {
+        driver::render_template(query, &self.template_ctx(), "queries")
+    }
+
+    // Statements does not imply _prepared_ statements
+    // in the name because a &str may contain multiple expressions.
+    //
+    // PostgreSQL has transactional DDL, so the statements run in a
+    // transaction, and a failing statement leaves nothing half applied.
+    fn apply_statements(&mut self, query: &str) -> Result<(), Error> {
+        let q = self.render_statements(query)?;
+        // Note, that transaction will be rolled back implicitly on Drop, if not committed.
+        let mut tx = match self.client.transaction() {
+            Ok(tx) => tx,
+            Err(e) => return Err(query_failed(e, "could not start transaction")),
+        };
+        if let Err(e) = tx.batch_execute(q.as_str()) {
+            return Err(query_failed(e, "Could not run the postgres query"));
+        }
+        match tx.commit() {
+            Ok(_) => {
+                info!("query {:?} ran successfully", q);
+                Ok(())
+            }
+            Err(e) => Err(query_failed(e, "could not commit transaction")),
+        }
+    }
+
+    fn bootstrap(&mut self) -> Result<(), Error> {
+        debug!("bootstrapping postgres migration storage");
+        for bootstrap_migration in self.bootstrap_migrations().iter() {
+            trace!("applying {:?}", bootstrap_migration);
+            if let Some(up) = bootstrap_migration.steps.get(&Direction::Up) {
+                self.apply_statements(&up.source)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_bootstrapped(&mut self) -> Result<bool, Error> {
+        match self.client.query_one(
+            "SELECT to_regclass($1) IS NOT NULL",
+            &[&MIGRATION_STATE_TABLE_NAME],
+        ) {
+            Ok(row) => Ok(row.get(0)),
+            Err(e) => Err(query_failed(e, "Checking for PostgreSQL table existance")),
+        }
+    }
+
+    // One lock per database, as that is where the migration state lives.
+    fn lock_name(&self) -> String {
+        format!(
+            "mitre_migration_lock.{}",
+            self.config.database.clone().unwrap_or_default()
+        )
+    }
+}
+
+impl Driver for PostgreSql {
+    fn run(&mut self, ms: &MigrationStep) -> Result<DriverResult, Error> {
+        self.apply_statements(&ms.source)?;
+        Ok(DriverResult::Success)
+    }
+
+    fn render(&self, ms: &MigrationStep) -> Result<String, Error> {
+        self.render_statements(&ms.source)
+    }
+
+    fn migration_template(&self) -> MigrationTemplate {
+        MigrationTemplate {
+            extension: "sql",
+            up: indoc!(
+                "
+                -- Put your migration here
+                CREATE TABLE your_table (
+                    column_one TEXT NOT NULL
+                );
+                "
+            ),
+            down: Some(indoc!(
+                "
+                DROP TABLE your_table;
+                "
+            )),
+        }
+    }
+
+    fn meta(&self) -> RunnerMeta<'static> {
+        crate::reserved::runner_by_name(crate::reserved::POSTGRESQL)
+            .expect("reserved word not found")
+    }
+}
+
+impl NamedDriver for PostgreSql {
+    fn name() -> &'static str {
+        "postgres"
+    }
+}
+
+impl MigrationList for PostgreSql {
+    // As with MySQL there is a deliberate 1+n query pattern here, first the
+    // migrations are listed, then their steps are collected one by one.
+    fn all(&mut self) -> Result<Box<(dyn Iterator<Item = Migration> + 'static)>, Error> {
+        if !self.is_bootstrapped()? {
+            info!("early return with empty migration list, we appear not to be initialized");
+            return Ok(Box::new(vec![].into_iter()));
+        }
+
+        let q = format!(
+            "SELECT version, flags, configuration_name, built_in FROM {t} ORDER BY version ASC",
+            t = MIGRATION_STATE_TABLE_NAME
+        );
+        let mut migrations = match self.client.query(q.as_str(), &[]) {
+            Ok(rows) => rows
+                .iter()
+                .map(|row| {
+                    Ok(Migration {
+                        date_time: version_date_time(row.get(0))?,
+                        flags: crate::reserved::flags_from_str_flags(&row.get::<_, String>(1)),
+                        configuration_name: row.get(2),
+                        built_in: row.get(3),
+                        steps: std::collections::HashMap::new(),
+                    })
+                })
+                .collect::<Result<Vec<Migration>, Error>>()?,
+            Err(e) => {
+                return Err(query_failed(
+                    e,
+                    "Querying list of PostgreSQL stored migrations",
+                ))
+            }
+        };
+
+        let q = format!(
+            "SELECT direction, source, path FROM {t} WHERE version = $1",
+            t = MIGRATION_STEPS_TABLE_NAME
+        );
+        for m in &mut migrations {
+            match self
+                .client
+                .query(q.as_str(), &[&version_number(&m.version())?])
+            {
+                Ok(rows) => {
+                    for row in rows {
+                        m.steps.insert(
+                            Direction::from(row.get::<_, String>(0)),
+                            MigrationStep {
+                                source: row.get(1),
+                                path: PathBuf::from(row.get::<_, String>(2)),
+                            },
+                        );
+                    }
+                }
+                Err(e) => {
+                    return Err(query_failed(
+                        e,
+                        "Querying list of PostgreSQL stored migration steps",
+                    ))
+                }
+            }
+        }
+
+        Ok(Box::new(migrations.into_iter()))
+    }
+}
+
+impl MigrationStorage for PostgreSql {
+    #[cfg(test)]
+    fn reset(&mut self) -> Result<(), Error> {
+        log::trace!("Running PostgreSQL reset.");
+        for bootstrap_migration in self.bootstrap_migrations().iter().rev() {
+            if let Some(down) = bootstrap_migration.steps.get(&Direction::Down) {
+                self.apply_statements(&down.source)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, m: Migration) -> Result<(), Error> {
+        trace!("add about to bootstrap");
+        self.bootstrap()?;
+
+        let version = version_number(&m.version())?;
+        // Note, that transaction will be rolled back implicitly on Drop, if not committed.
+        let mut tx = match self.client.transaction() {
+            Ok(tx) => tx,
+            Err(e) => return Err(query_failed(e, "could not start transaction")),
+        };
+        let q = indoc::formatdoc!(
+            "
+          INSERT INTO {}
+          (
+            version,
+            stored_at,
+            flags,
+            configuration_name,
+            built_in
+          )
+          VALUES ( $1, CAST($2::TEXT AS TIMESTAMP), $3, $4, $5 )
+          ON CONFLICT (version) DO UPDATE SET
+            stored_at = EXCLUDED.stored_at,
+            flags = EXCLUDED.flags,
+            configuration_name = EXCLUDED.configuration_name,
+            built_in = EXCLUDED.built_in;",
+            MIGRATION_STATE_TABLE_NAME
+        );
+        if let Err(e) = tx.execute(
+            q.as_str(),
+            &[
+                &version,
+                &Utc::now().format(DATE_TIME_FORMAT_STR).to_string(),
+                &m.flags_as_string(),
+                &m.configuration_name,
+                &m.built_in,
+            ],
+        ) {
+            return Err(query_failed(
+                e,
+                "could not insert migration into migration state table",
+            ));
+        }
+        // Storing a migration again replaces its steps, rather than adding to them
+        if let Err(e) = tx.execute(
+            format!(
+                "DELETE FROM {} WHERE version = $1",
+                MIGRATION_STEPS_TABLE_NAME
+            )
+            .as_str(),
+            &[&version],
+        ) {
+            return Err(query_failed(
+                e,
+                "could not remove previous migration steps from migration steps table",
+            ));
+        }
+        let q = indoc::formatdoc!(
+            "
+          INSERT INTO {}
+          (
+            version,
+            direction,
+            source,
+            path
+          )
+          VALUES ( $1, $2, $3, $4 );",
+            MIGRATION_STEPS_TABLE_NAME
+        );
+        for (direction, s) in &m.steps {
+            if let Err(e) = tx.execute(
+                q.as_str(),
+                &[
+                    &version,
+                    &format!("{:?}", direction).to_lowercase(),
+                    &s.source,
+                    &s.path.to_string_lossy().into_owned(),
+                ],
+            ) {
+                return Err(query_failed(
+                    e,
+                    "could not insert migration steps into migration steps table",
+                ));
+            }
+        }
+        tx.commit()
+            .map_err(|e| query_failed(e, "could not commit transaction"))
+    }
+
+    fn remove(&mut self, m: Migration) -> Result<(), Error> {
+        trace!("remove about to bootstrap");
+        self.bootstrap()?;
+
+        let version = version_number(&m.version())?;
+        let mut tx = match self.client.transaction() {
+            Ok(tx) => tx,
+            Err(e) => return Err(query_failed(e, "could not start transaction")),
+        };
+        // Steps first, they reference the migration
+        let mut removed = 0;
+        for table in &[MIGRATION_STEPS_TABLE_NAME, MIGRATION_STATE_TABLE_NAME] {
+            removed = match tx.execute(
+                format!("DELETE FROM {} WHERE version = $1", table).as_str(),
+                &[&version],
+            ) {
+                Ok(removed) => removed,
+                Err(e) => return Err(query_failed(e, "could not remove migration")),
+            };
+        }
+        if removed == 0 {
+            return Err(Error::MigrationNotFound {
+                version: m.version(),
+            });
+        }
+        tx.commit()
+            .map_err(|e| query_failed(e, "could not commit transaction"))
+    }
+
+    // Session level advisory locks are held by the connection, and released
+    // by the server should this process die whilst holding one. There is no
+    // waiting variant with a timeout, so poll the non-blocking one.
+    fn lock(&mut self, timeout: Duration) -> Result<(), Error> {
+        let name = self.lock_name();
+        let started = std::time::Instant::now();
+        loop {
+            match self
+                .client
+                .query_one("SELECT pg_try_advisory_lock(hashtext($1))", &[&name])
+            {
+                Ok(row) if row.get::<_, bool>(0) => return Ok(()),
+                Ok(_) if started.elapsed() >= timeout => {
+                    return Err(Error::LockTimeout { timeout })
+                }
+                Ok(_) => std::thread::sleep(LOCK_POLL_INTERVAL),
+                Err(e) => return Err(query_failed(e, "could not acquire migration lock")),
+            }
+        }
+    }
+
+    fn unlock(&mut self) -> Result<(), Error> {
+        let name = self.lock_name();
+        match self
+            .client
+            .execute("SELECT pg_advisory_unlock(hashtext($1))", &[&name])
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(query_failed(e, "could not release migration lock")),
+        }
+    }
+
+    fn record(&mut self, entry: HistoryEntry) -> Result<(), Error> {
+        // A failed attempt may well be the first time this storage is used
+        self.bootstrap()?;
+        let q = indoc::formatdoc!(
+            "
+          INSERT INTO {}
+          (
+            version,
+            direction,
+            started_at,
+            finished_at,
+            outcome,
+            error,
+            hostname,
+            mitre_version
+          )
+          VALUES ( $1, $2, CAST($3::TEXT AS TIMESTAMP), CAST($4::TEXT AS TIMESTAMP), $5, $6, $7, $8 );",
+            MIGRATION_HISTORY_TABLE_NAME
+        );
+        match self.client.execute(
+            q.as_str(),
+            &[
+                &version_number(&entry.version)?,
+                &format!("{:?}", entry.direction).to_lowercase(),
+                &entry.started_at.format(DATE_TIME_FORMAT_STR).to_string(),
+                &entry.finished_at.format(DATE_TIME_FORMAT_STR).to_string(),
+                &entry.outcome,
+                &entry.error,
+                &entry.hostname,
+                &entry.mitre_version,
+            ],
+        ) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(query_failed(
+                e,
+                "could not insert into migration history table",
+            )),
+        }
+    }
+
+    fn history(&mut self) -> Result<Vec<HistoryEntry>, Error> {
+        self.bootstrap()?;
+        let q = format!(
+            "SELECT version, direction, to_char(started_at, '{f}'), to_char(finished_at, '{f}'), outcome, error, hostname, mitre_version FROM {t} ORDER BY id ASC",
+            f = TO_CHAR_FORMAT_STR,
+            t = MIGRATION_HISTORY_TABLE_NAME
+        );
+        match self.client.query(q.as_str(), &[]) {
+            Ok(rows) => rows
+                .into_iter()
+                .map(|row| {
+                    let version = row.get::<_, i64>(0);
+                    let direction = row.get::<_, String>(1);
+                    let entry = format!("migration history entry of {} ({})", version, direction);
+                    Ok(HistoryEntry {
+                        version: version.to_string(),
+                        direction: Direction::from(direction),
+                        started_at: parse_date_time(row.get(2), &entry)?,
+                        finished_at: parse_date_time(row.get(3), &entry)?,
+                        outcome: row.get(4),
+                        error: row.get(5),
+                        hostname: row.get(6),
+                        mitre_version: row.get(7),
+                    })
+                })
+                .collect(),
+            Err(e) => Err(query_failed(e, "Querying the PostgreSQL migration history")),
+        }
+    }
+}
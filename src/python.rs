@@ -0,0 +1,279 @@
+use super::{
+    driver, migrations::MigrationStep, script, Driver, DriverResult, Error, MigrationTemplate,
+    NamedDriver, RunnerConfiguration, RunnerMeta,
+};
+use indoc::indoc;
+use log::info;
+use std::path::Path;
+use std::process::Command;
+
+const DEFAULT_INTERPRETER: &str = "python3";
+
+/// Runs `.py` and `.py3` migrations, e.g. data transformations which are
+/// ordered and tracked alongside the SQL migrations for the same store. The
+/// rendered script is written to a temporary file, and run as
+/// `<interpreter> <file> <path>`, so the migration's path is `sys.argv[1]`,
+/// and the configuration is given as a JSON object on stdin:
+///
+/// ```text
+/// import json, sys
+/// config = json.load(sys.stdin)
+/// print("backfilling", config["database"])
+/// ```
+///
+/// The `interpreter` (with any arguments) is used if configured, otherwise
+/// the `python` of the configured `virtualenv`, or `python3` from the PATH.
+/// Scripts running for longer than their `# mitre-timeout: <seconds>` header,
+/// or else the configured `timeout` (seconds), are killed.
+pub struct Python {
+    config: RunnerConfiguration,
+}
+
+impl Python {
+    pub fn new(config: RunnerConfiguration) -> Result<Self, Error> {
+        Ok(Python { config })
+    }
+
+    fn command(&self) -> Command {
+        match (&self.config.interpreter, &self.config.virtualenv) {
+            (Some(interpreter), _) => script::command(interpreter),
+            // Running the virtualenv's own python is all that activating it
+            // would do for us, as far as imports are concerned.
+            (None, Some(virtualenv)) => {
+                let mut command = Command::new(Path::new(virtualenv).join("bin").join("python"));
+                command.env("VIRTUAL_ENV", virtualenv);
+                command
+            }
+            (None, None) => Command::new(DEFAULT_INTERPRETER),
+        }
+    }
+
+    fn template_ctx(&self) -> mustache::Data {
+        mustache::MapBuilder::new()
+            .insert_str("database", self.config.database.clone().unwrap_or_default())
+            .insert_str(
+                "ip_or_hostname",
+                self.config.ip_or_hostname.clone().unwrap_or_default(),
+            )
+            .build()
+    }
+
+    fn render_script(&self, script: &str) -> Result<String, Error> {
+        driver::render_template(script, &self.template_ctx(), "script")
+    }
+}
+
+impl Driver for Python {
+    fn run(&mut self, ms: &MigrationStep) -> Result<DriverResult, Error> {
+        let script = self.render_script(&ms.source)?;
+        let file = script::script_file(&script)?;
+        let mut command = self.command();
+        command.arg(file.path()).arg(&ms.path);
+
+        info!("running {}", ms.path.display());
+        let timeout = script::timeout(&ms.source, self.config.timeout)?;
+        let config = self.config.to_json().to_string();
+        let (stdout, stderr) = script::run(command, &ms.path, Some(config.as_bytes()), timeout)?;
+        script::log_output(&ms.path, &stdout, &stderr);
+        Ok(DriverResult::Success)
+    }
+
+    fn render(&self, ms: &MigrationStep) -> Result<String, Error> {
+        self.render_script(&ms.source)
+    }
+
+    fn migration_template(&self) -> MigrationTemplate {
+        MigrationTemplate {
+            extension: "py",
+            up: indoc!(
+                r#"
+                import json, sys
+
+                config = json.load(sys.stdin)
+                # Put your migration here
+                "#
+            ),
+            down: Some(indoc!(
+                r#"
+                import json, sys
+
+                config = json.load(sys.stdin)
+                "#
+            )),
+        }
+    }
+
+    fn meta(&self) -> RunnerMeta<'static> {
+        crate::reserved::runner_by_name(crate::reserved::PYTHON_3).expect("reserved word not found")
+    }
+}
+
+impl NamedDriver for Python {
+    fn name() -> &'static str {
+        "python3"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Direction, Migration};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    fn config() -> RunnerConfiguration {
+        RunnerConfiguration {
+            _driver: String::from("python3"),
+            database: Some(String::from("warehouse")),
+            ip_or_hostname: Some(String::from("127.0.0.1")),
+            password: Some(String::from("secret")),
+            port: Some(5432),
+            timeout: Some(10),
+            ..Default::default()
+        }
+    }
+
+    fn migration(steps: Vec<(Direction, &str)>) -> Migration {
+        let mut m = HashMap::new();
+        for (direction, source) in steps {
+            let path = match direction {
+                Direction::Down => "down.py",
+                _ => "up.py",
+            };
+            m.insert(
+                direction,
+                MigrationStep {
+                    path: PathBuf::from("/foo/20210503213400_backfill_orders.warehouse").join(path),
+                    source: String::from(source),
+                },
+            );
+        }
+        Migration {
+            date_time: chrono::Utc::now().naive_utc(),
+            steps: m,
+            built_in: false,
+            flags: vec![],
+            configuration_name: String::from("warehouse"),
+        }
+    }
+
+    #[test]
+    fn passes_the_configuration_as_json_on_stdin() -> Result<(), String> {
+        let mut python = Python::new(config()).map_err(|e| format!("{:?}", e))?;
+        let m = migration(vec![(
+            Direction::Up,
+            r#"
+import json, sys
+config = json.load(sys.stdin)
+assert config["database"] == "warehouse", config
+assert config["port"] == 5432, config
+assert config["username"] is None, config
+assert sys.argv[1].endswith("up.py"), sys.argv
+"#,
+        )]);
+        match python.apply(&m) {
+            Ok(DriverResult::Success) => Ok(()),
+            Ok(_) => Err(String::from("expected the migration to succeed")),
+            Err(e) => Err(format!("{:?}", e)),
+        }
+    }
+
+    #[test]
+    fn runs_down_and_fails_with_the_exit_code() -> Result<(), String> {
+        let mut python = Python::new(config()).map_err(|e| format!("{:?}", e))?;
+        let m = migration(vec![
+            (Direction::Up, "pass"),
+            (
+                Direction::Down,
+                "import sys\nprint('irreversible')\nsys.exit(4)",
+            ),
+        ]);
+        match python.unapply(&m) {
+            Err(Error::ScriptFailed {
+                path,
+                exit_code,
+                stdout,
+                ..
+            }) => {
+                assert!(path.ends_with("down.py"));
+                assert_eq!(exit_code, Some(4));
+                assert_eq!(stdout, "irreversible\n");
+            }
+            Err(e) => return Err(format!("unexpected error {:?}", e)),
+            Ok(_) => return Err(String::from("a non-zero exit was treated as success")),
+        }
+        match python.unapply(&migration(vec![(Direction::Up, "pass")])) {
+            Ok(DriverResult::NothingToDo) => Ok(()),
+            Ok(_) => Err(String::from("expected nothing to do without a down.py")),
+            Err(e) => Err(format!("{:?}", e)),
+        }
+    }
+
+    #[test]
+    fn kills_migrations_past_the_timeout_in_their_header() -> Result<(), String> {
+        let mut python = Python::new(config()).map_err(|e| format!("{:?}", e))?;
+        let m = migration(vec![(
+            Direction::Up,
+            "# mitre-timeout: 1\nimport time\ntime.sleep(10)",
+        )]);
+        match python.apply(&m) {
+            Err(Error::ScriptTimedOut { timeout, .. }) => {
+                assert_eq!(timeout, std::time::Duration::from_secs(1));
+                Ok(())
+            }
+            Err(e) => Err(format!("unexpected error {:?}", e)),
+            Ok(_) => Err(String::from("the migration was not killed")),
+        }
+    }
+
+    #[test]
+    fn uses_the_virtualenvs_python() -> Result<(), String> {
+        let venv = tempfile::tempdir().map_err(|e| e.to_string())?;
+        let bin = venv.path().join("bin");
+        std::fs::create_dir(&bin).map_err(|e| e.to_string())?;
+        // Not a real virtualenv, but it has a python which tells us when it
+        // was the one which was run.
+        std::fs::write(
+            bin.join("python"),
+            "#!/bin/sh\necho \"from the virtualenv $VIRTUAL_ENV\"\nexit 9\n",
+        )
+        .map_err(|e| e.to_string())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(bin.join("python"), std::fs::Permissions::from_mode(0o755))
+                .map_err(|e| e.to_string())?;
+        }
+        let virtualenv = venv.path().to_string_lossy().into_owned();
+        let mut python = Python::new(RunnerConfiguration {
+            virtualenv: Some(virtualenv.clone()),
+            ..config()
+        })
+        .map_err(|e| format!("{:?}", e))?;
+        match python.apply(&migration(vec![(Direction::Up, "pass")])) {
+            Err(Error::ScriptFailed {
+                exit_code, stdout, ..
+            }) => {
+                assert_eq!(exit_code, Some(9));
+                assert_eq!(stdout, format!("from the virtualenv {}\n", virtualenv));
+                Ok(())
+            }
+            Err(e) => Err(format!("unexpected error {:?}", e)),
+            Ok(_) => Err(String::from("the virtualenv's python was not run")),
+        }
+    }
+
+    #[test]
+    fn runs_scripts_longer_than_an_argument_may_be() -> Result<(), String> {
+        let mut python = Python::new(config()).map_err(|e| format!("{:?}", e))?;
+        let script = format!(
+            "# {}\nimport sys\nassert sys.argv[1].endswith(\"up.py\"), sys.argv",
+            "x".repeat(200 * 1024)
+        );
+        match python.apply(&migration(vec![(Direction::Up, &script)])) {
+            Ok(DriverResult::Success) => Ok(()),
+            Ok(_) => Err(String::from("expected the migration to succeed")),
+            Err(e) => Err(format!("{:?}", e)),
+        }
+    }
+}
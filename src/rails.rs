@@ -0,0 +1,225 @@
+use super::{
+    driver, migrations::MigrationStep, script, Driver, DriverResult, Error, MigrationTemplate,
+    NamedDriver, RunnerConfiguration, RunnerMeta,
+};
+use indoc::indoc;
+use log::info;
+use std::path::Path;
+use std::process::Command;
+
+/// Runs `.rb` migrations inside a Rails app, for apps whose data migrations
+/// need the app's models. The app is the configured `path`, the rendered
+/// migration is given to `bin/rails runner` there (or the configured
+/// `interpreter`, e.g "bundle exec rails runner") as code, with the
+/// migration's path as `ARGV[0]`, and `RAILS_ENV` set from `rails_env`:
+///
+/// ```text
+/// Order.where(total_cents: nil).find_each { |o| o.update!(total_cents: 0) }
+/// ```
+///
+/// Scripts running for longer than their `# mitre-timeout: <seconds>` header,
+/// or else the configured `timeout` (seconds), are killed.
+pub struct Rails {
+    config: RunnerConfiguration,
+}
+
+impl Rails {
+    pub fn new(config: RunnerConfiguration) -> Result<Self, Error> {
+        match config.path {
+            Some(_) => Ok(Rails { config }),
+            None => Err(Error::ConfigurationIncomplete),
+        }
+    }
+
+    fn app(&self) -> &Path {
+        Path::new(self.config.path.as_deref().unwrap_or("."))
+    }
+
+    fn command(&self) -> Command {
+        let mut command = match &self.config.interpreter {
+            Some(interpreter) => script::command(interpreter),
+            // Joined explicitly, whether a relative program is looked up in
+            // the child's working directory or ours depends on the platform.
+            None => {
+                let mut command = Command::new(self.app().join("bin").join("rails"));
+                command.arg("runner");
+                command
+            }
+        };
+        command.current_dir(self.app());
+        if let Some(rails_env) = &self.config.rails_env {
+            command.env("RAILS_ENV", rails_env);
+        }
+        command
+    }
+
+    fn template_ctx(&self) -> mustache::Data {
+        mustache::MapBuilder::new()
+            .insert_str(
+                "rails_env",
+                self.config.rails_env.clone().unwrap_or_default(),
+            )
+            .build()
+    }
+
+    fn render_script(&self, script: &str) -> Result<String, Error> {
+        driver::render_template(script, &self.template_ctx(), "script")
+    }
+}
+
+impl Driver for Rails {
+    fn run(&mut self, ms: &MigrationStep) -> Result<DriverResult, Error> {
+        let script = self.render_script(&ms.source)?;
+        let mut command = self.command();
+        command.arg(&script).arg(&ms.path);
+
+        info!("running {} in {}", ms.path.display(), self.app().display());
+        let timeout = script::timeout(&ms.source, self.config.timeout)?;
+        let (stdout, stderr) = script::run(command, &ms.path, None {}, timeout)?;
+        script::log_output(&ms.path, &stdout, &stderr);
+        Ok(DriverResult::Success)
+    }
+
+    fn render(&self, ms: &MigrationStep) -> Result<String, Error> {
+        self.render_script(&ms.source)
+    }
+
+    fn migration_template(&self) -> MigrationTemplate {
+        MigrationTemplate {
+            extension: "rb",
+            up: indoc!(
+                "
+                # Put your migration here, e.g
+                # Order.where(total_cents: nil).update_all(total_cents: 0)
+                "
+            ),
+            down: Some(indoc!(
+                "
+                # Order.where(total_cents: 0).update_all(total_cents: nil)
+                "
+            )),
+        }
+    }
+
+    fn meta(&self) -> RunnerMeta<'static> {
+        crate::reserved::runner_by_name(crate::reserved::RAILS).expect("reserved word not found")
+    }
+}
+
+impl NamedDriver for Rails {
+    fn name() -> &'static str {
+        "rails"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Direction, Migration};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    // An app with a bin/rails which records how it was run in runs.log,
+    // and fails when asked to run `raise`.
+    fn stub_app() -> Result<tempfile::TempDir, String> {
+        let app = tempfile::tempdir().map_err(|e| e.to_string())?;
+        let bin = app.path().join("bin");
+        std::fs::create_dir(&bin).map_err(|e| e.to_string())?;
+        std::fs::write(
+            bin.join("rails"),
+            indoc::indoc! {r#"
+                #!/bin/sh
+                echo "$RAILS_ENV $(basename "$(pwd)") $1 $2 $(basename "$3")" >> runs.log
+                test "$2" != "raise" || { echo "RuntimeError (unhandled exception)" >&2; exit 1; }
+            "#},
+        )
+        .map_err(|e| e.to_string())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(bin.join("rails"), std::fs::Permissions::from_mode(0o755))
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(app)
+    }
+
+    fn config(app: &Path) -> RunnerConfiguration {
+        RunnerConfiguration {
+            _driver: String::from("rails"),
+            path: Some(app.to_string_lossy().into_owned()),
+            timeout: Some(10),
+            rails_env: Some(String::from("staging")),
+            ..Default::default()
+        }
+    }
+
+    fn migration(up: &str, down: &str) -> Migration {
+        let mut steps = HashMap::new();
+        for (direction, file, source) in std::array::IntoIter::new([
+            (Direction::Up, "up.rb", up),
+            (Direction::Down, "down.rb", down),
+        ]) {
+            steps.insert(
+                direction,
+                MigrationStep {
+                    path: PathBuf::from("/foo/20210503213400_backfill_totals.monolith").join(file),
+                    source: String::from(source),
+                },
+            );
+        }
+        Migration {
+            date_time: chrono::Utc::now().naive_utc(),
+            steps,
+            built_in: false,
+            flags: vec![],
+            configuration_name: String::from("monolith"),
+        }
+    }
+
+    #[test]
+    fn needs_the_path_of_the_app() {
+        let config = RunnerConfiguration {
+            path: None {},
+            ..config(Path::new("."))
+        };
+        assert!(matches!(
+            Rails::new(config),
+            Err(Error::ConfigurationIncomplete)
+        ));
+    }
+
+    #[test]
+    fn runs_up_and_down_with_rails_runner_in_the_app() -> Result<(), String> {
+        let app = stub_app()?;
+        let mut rails = Rails::new(config(app.path())).map_err(|e| format!("{:?}", e))?;
+        let m = migration("Order.count", "Rails.env");
+        rails.apply(&m).map_err(|e| format!("{:?}", e))?;
+        rails.unapply(&m).map_err(|e| format!("{:?}", e))?;
+        let app_name = app.path().file_name().unwrap().to_string_lossy();
+        assert_eq!(
+            std::fs::read_to_string(app.path().join("runs.log")).map_err(|e| e.to_string())?,
+            format!(
+                "staging {} runner Order.count up.rb\nstaging {} runner Rails.env down.rb\n",
+                app_name, app_name
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_with_the_output_of_rails() -> Result<(), String> {
+        let app = stub_app()?;
+        let mut rails = Rails::new(config(app.path())).map_err(|e| format!("{:?}", e))?;
+        match rails.apply(&migration("raise", "")) {
+            Err(Error::ScriptFailed {
+                exit_code, stderr, ..
+            }) => {
+                assert_eq!(exit_code, Some(1));
+                assert_eq!(stderr, "RuntimeError (unhandled exception)\n");
+                Ok(())
+            }
+            Err(e) => Err(format!("unexpected error {:?}", e)),
+            Ok(_) => Err(String::from("a non-zero exit was treated as success")),
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use super::{
+    driver, migrations::MigrationStep, Driver, DriverResult, Error, MigrationTemplate, NamedDriver,
+    RunnerConfiguration, RunnerMeta,
+};
+use indoc::indoc;
+use log::{debug, info, trace};
+
+// TCP ports are unsigned 16 bit ints
+// https://tools.ietf.org/html/rfc793#section-3.1
+const REDIS_DEFAULT_PORT: u16 = 6379;
+
+/// Runs `.redis` migrations, one command per line, e.g. for feature flags or
+/// keys which are kept in Redis.
+pub struct Redis {
+    conn: redis::Connection,
+    config: RunnerConfiguration,
+}
+
+// Splits a line into a command and its arguments the way redis-cli does, on
+// whitespace, keeping "double quoted" (with \n, \t, \r, \" and \\ escapes)
+// and 'single quoted' arguments together.
+fn split_command(line: &str) -> Result<Vec<String>, String> {
+    let mut args = vec![];
+    let mut chars = line.chars().peekable();
+    loop {
+        while matches!(chars.peek(), Some(c) if c.is_whitespace()) {
+            chars.next();
+        }
+        let quote = match chars.peek() {
+            None => return Ok(args),
+            Some('"') | Some('\'') => chars.next(),
+            Some(_) => None {},
+        };
+        let mut arg = String::new();
+        loop {
+            match (quote, chars.next()) {
+                (None, None) => break,
+                (None, Some(c)) if c.is_whitespace() => break,
+                (Some(q), None) => return Err(format!("unterminated {} quoted argument", q)),
+                (Some(q), Some(c)) if c == q => match chars.peek() {
+                    Some(c) if !c.is_whitespace() => {
+                        return Err(String::from("closing quote must be followed by a space"))
+                    }
+                    _ => break,
+                },
+                (Some('"'), Some('\\')) => match chars.next() {
+                    Some('n') => arg.push('\n'),
+                    Some('t') => arg.push('\t'),
+                    Some('r') => arg.push('\r'),
+                    Some(c) => arg.push(c),
+                    None => return Err(String::from("unterminated \" quoted argument")),
+                },
+                (_, Some(c)) => arg.push(c),
+            }
+        }
+        args.push(arg);
+    }
+}
+
+// A command and its arguments, with the (1-based) line it came from
+type Command = (usize, Vec<String>);
+
+// The commands in a migration, blank lines and lines starting with # are
+// skipped. Errors carry the line number too.
+fn commands(source: &str) -> Result<Vec<Command>, (usize, String)> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(n, line)| (n + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| {
+            split_command(line)
+                .map(|args| (n, args))
+                .map_err(|e| (n, e))
+        })
+        .collect()
+}
+
+impl Redis {
+    pub fn new(config: RunnerConfiguration) -> Result<Self, Error> {
+        let info = redis::ConnectionInfo {
+            addr: redis::ConnectionAddr::Tcp(
+                config
+                    .ip_or_hostname
+                    .clone()
+                    .unwrap_or_else(|| std::net::Ipv4Addr::LOCALHOST.to_string()),
+                config.port.unwrap_or(REDIS_DEFAULT_PORT),
+            ),
+            redis: redis::RedisConnectionInfo {
+                // Selected explicitly below, rather than by the client
+                db: 0,
+                username: config.username.clone(),
+                password: config.password.clone(),
+            },
+        };
+        info!("connecting to redis {:?}", info.addr);
+        let mut conn = match redis::Client::open(info).and_then(|c| c.get_connection()) {
+            Ok(conn) => conn,
+            Err(e) => {
+                return Err(Error::ConnectionError {
+                    msg: format!("Connecting to Redis: {}", e),
+                })
+            }
+        };
+        if let Some(database_number) = config.database_number {
+            debug!("selecting redis database {}", database_number);
+            if let Err(e) = redis::cmd("SELECT")
+                .arg(database_number)
+                .query::<()>(&mut conn)
+            {
+                return Err(Error::ConnectionError {
+                    msg: format!("Selecting Redis database {}: {}", database_number, e),
+                });
+            }
+        }
+        Ok(Redis { conn, config })
+    }
+
+    fn template_ctx(&self) -> mustache::Data {
+        mustache::MapBuilder::new()
+            .insert_str(
+                "database_number",
+                self.config.database_number.unwrap_or_default().to_string(),
+            )
+            .build()
+    }
+
+    fn render_statements(&self, query: &str) -> Result<String, Error> {
+        driver::render_template(query, &self.template_ctx(), "commands")
+    }
+}
+
+impl Driver for Redis {
+    // Redis has no transactions which roll back a failed command, so the
+    // commands before a failing one stay applied, the error names the line
+    // of the failing command so that it can be fixed, or cleaned up.
+    fn run(&mut self, ms: &MigrationStep) -> Result<DriverResult, Error> {
+        let source = self.render_statements(&ms.source)?;
+        let commands = commands(&source).map_err(|(n, e)| Error::QueryFailed {
+            reason: None {},
+            msg: format!("{}:{}: {}", ms.path.display(), n, e),
+        })?;
+        for (n, args) in commands {
+            trace!("running redis command {:?} from line {}", args, n);
+            let mut cmd = redis::cmd(&args[0]);
+            for arg in &args[1..] {
+                cmd.arg(arg);
+            }
+            match cmd.query::<redis::Value>(&mut self.conn) {
+                Ok(reply) => debug!("line {}: {:?}", n, reply),
+                Err(e) => {
+                    return Err(Error::QueryFailed {
+                        reason: None {},
+                        msg: format!("{}:{}: {} failed: {}", ms.path.display(), n, args[0], e),
+                    })
+                }
+            }
+        }
+        Ok(DriverResult::Success)
+    }
+
+    fn render(&self, ms: &MigrationStep) -> Result<String, Error> {
+        self.render_statements(&ms.source)
+    }
+
+    fn migration_template(&self) -> MigrationTemplate {
+        MigrationTemplate {
+            extension: "redis",
+            up: indoc!(
+                "
+                # Put your migration here, one command per line
+                SET your_key your_value
+                "
+            ),
+            down: Some(indoc!(
+                "
+                DEL your_key
+                "
+            )),
+        }
+    }
+
+    fn meta(&self) -> RunnerMeta<'static> {
+        crate::reserved::runner_by_name(crate::reserved::REDIS).expect("reserved word not found")
+    }
+}
+
+impl NamedDriver for Redis {
+    fn name() -> &'static str {
+        "redis"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_commands_like_redis_cli() {
+        assert_eq!(
+            split_command(r#"SET "feature:new checkout" 'on' EX 60"#),
+            Ok(vec![
+                String::from("SET"),
+                String::from("feature:new checkout"),
+                String::from("on"),
+                String::from("EX"),
+                String::from("60"),
+            ])
+        );
+        assert_eq!(
+            split_command(r#"SET greeting "hello\n\"world\"""#),
+            Ok(vec![
+                String::from("SET"),
+                String::from("greeting"),
+                String::from("hello\n\"world\""),
+            ])
+        );
+        assert!(split_command(r#"SET foo "bar"#).is_err());
+        assert!(split_command(r#"SET foo "bar"baz"#).is_err());
+    }
+
+    #[test]
+    fn numbers_commands_by_line_skipping_blanks_and_comments() {
+        let source = "# feature flags\nSET a 1\n\n  DEL b  \nSET c \"oops\n";
+        assert_eq!(
+            commands(source).err(),
+            Some((5, String::from("unterminated \" quoted argument")))
+        );
+        assert_eq!(
+            commands("# feature flags\nSET a 1\n\n  DEL b  \n"),
+            Ok(vec![
+                (
+                    2,
+                    vec![String::from("SET"), String::from("a"), String::from("1")]
+                ),
+                (4, vec![String::from("DEL"), String::from("b")]),
+            ])
+        );
+    }
+}
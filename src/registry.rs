@@ -0,0 +1,267 @@
+//! The drivers and migration storages which `_driver` in the configuration
+//! may name. The built-in ones are registered according to the enabled cargo
+//! features, applications embedding mitre may register their own before
+//! making an [`crate::Engine`]:
+//!
+//! ```ignore
+//! mitre::register_driver(
+//!     RunnerMeta { name: "Mongo", desc: "MongoDB, by us", exts: vec!["js"] },
+//!     &["mongodb"],
+//!     |config| Ok(Box::new(Mongo::new(config)?)),
+//! );
+//! ```
+//!
+//! Names are matched case insensitively, registering a name again replaces
+//! whatever was registered under it before, built-ins included.
+
+use super::{Driver, Error, FileStorage, MigrationStorage, MySQL, RunnerConfiguration, RunnerMeta};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Makes a driver from the configuration which names it.
+pub type DriverConstructor = fn(RunnerConfiguration) -> Result<Box<dyn Driver>, Error>;
+
+/// Makes a migration storage from the `mitre` configuration.
+pub type StorageConstructor = fn(RunnerConfiguration) -> Result<Box<dyn MigrationStorage>, Error>;
+
+struct Registry {
+    drivers: HashMap<String, (RunnerMeta<'static>, DriverConstructor)>,
+    storages: HashMap<String, StorageConstructor>,
+}
+
+lazy_static! {
+    static ref REGISTRY: RwLock<Registry> = RwLock::new(Registry::with_built_ins());
+}
+
+impl Registry {
+    fn add_driver(
+        &mut self,
+        meta: RunnerMeta<'static>,
+        aliases: &[&str],
+        constructor: DriverConstructor,
+    ) {
+        for name in std::iter::once(&meta.name).chain(aliases) {
+            self.drivers
+                .insert(name.to_lowercase(), (meta.clone(), constructor));
+        }
+    }
+
+    fn add_storage(&mut self, names: &[&str], constructor: StorageConstructor) {
+        for name in names {
+            self.storages.insert(name.to_lowercase(), constructor);
+        }
+    }
+
+    fn with_built_ins() -> Self {
+        let mut r = Registry {
+            drivers: HashMap::new(),
+            storages: HashMap::new(),
+        };
+
+        r.add_driver(reserved(crate::reserved::MYSQL), &[], |c| {
+            Ok(Box::new(MySQL::new(c)?))
+        });
+        r.add_driver(reserved(crate::reserved::MARIA_DB), &[], |c| {
+            Ok(Box::new(MySQL::new(c)?))
+        });
+        #[cfg(feature = "runner_bash")]
+        {
+            r.add_driver(reserved(crate::reserved::BASH_3), &[], |c| {
+                Ok(Box::new(crate::Bash::new(c)?))
+            });
+            r.add_driver(reserved(crate::reserved::BASH_4), &[], |c| {
+                Ok(Box::new(crate::Bash::new(c)?))
+            });
+        }
+        #[cfg(feature = "runner_python")]
+        r.add_driver(reserved(crate::reserved::PYTHON_3), &[], |c| {
+            Ok(Box::new(crate::Python::new(c)?))
+        });
+        #[cfg(feature = "runner_rails")]
+        r.add_driver(reserved(crate::reserved::RAILS), &[], |c| {
+            Ok(Box::new(crate::Rails::new(c)?))
+        });
+        #[cfg(feature = "runner_kafka")]
+        r.add_driver(reserved(crate::reserved::KAFKA), &[], |c| {
+            Ok(Box::new(crate::Kafka::new(c)?))
+        });
+        #[cfg(feature = "runner_elasticsearch")]
+        r.add_driver(reserved(crate::reserved::ELASTICSEARCH), &[], |c| {
+            Ok(Box::new(crate::Elasticsearch::new(c)?))
+        });
+        #[cfg(feature = "runner_http")]
+        r.add_driver(reserved(crate::reserved::HTTP), &[], |c| {
+            Ok(Box::new(crate::Http::new(c)?))
+        });
+        #[cfg(feature = "runner_postgres")]
+        r.add_driver(reserved(crate::reserved::POSTGRESQL), &[], |c| {
+            Ok(Box::new(crate::PostgreSql::new(c)?))
+        });
+        #[cfg(feature = "runner_redis")]
+        r.add_driver(reserved(crate::reserved::REDIS), &[], |c| {
+            Ok(Box::new(crate::Redis::new(c)?))
+        });
+        #[cfg(feature = "runner_sqlite")]
+        r.add_driver(reserved(crate::reserved::SQLITE), &[], |c| {
+            Ok(Box::new(crate::Sqlite::new(c)?))
+        });
+        // Lets the Engine be tested end-to-end without any external services.
+        #[cfg(test)]
+        {
+            use crate::driver::{noop, succeed_or_fail};
+            r.add_driver((noop::Driver {}).meta(), &[], |_| {
+                Ok(Box::new(noop::Driver {}))
+            });
+            r.add_driver((succeed_or_fail::Driver {}).meta(), &[], |_| {
+                Ok(Box::new(succeed_or_fail::Driver {}))
+            });
+        }
+
+        r.add_storage(&[crate::reserved::MYSQL, crate::reserved::MARIA_DB], |c| {
+            Ok(Box::new(MySQL::new(c)?))
+        });
+        #[cfg(feature = "runner_postgres")]
+        r.add_storage(&[crate::reserved::POSTGRESQL], |c| {
+            Ok(Box::new(crate::PostgreSql::new(c)?))
+        });
+        #[cfg(feature = "runner_sqlite")]
+        r.add_storage(&[crate::reserved::SQLITE], |c| {
+            Ok(Box::new(crate::Sqlite::new(c)?))
+        });
+        r.add_storage(&[crate::reserved::FILE], |c| {
+            Ok(Box::new(FileStorage::new(c)?))
+        });
+        r
+    }
+}
+
+fn reserved(name: &'static str) -> RunnerMeta<'static> {
+    crate::reserved::runner_by_name(name).expect("reserved word not found")
+}
+
+// Registrations can't be left half done, so a panic elsewhere while the lock
+// was held doesn't make the registry unusable.
+fn read() -> std::sync::RwLockReadGuard<'static, Registry> {
+    REGISTRY.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write() -> std::sync::RwLockWriteGuard<'static, Registry> {
+    REGISTRY.write().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Register a driver under the name of its [`RunnerMeta`], and any aliases,
+/// the extensions of the meta are the migrations it is given.
+pub fn register_driver(
+    meta: RunnerMeta<'static>,
+    aliases: &[&str],
+    constructor: DriverConstructor,
+) {
+    write().add_driver(meta, aliases, constructor)
+}
+
+/// Register a migration storage, for when the `mitre` configuration names
+/// any of `names` as its `_driver`.
+pub fn register_storage(names: &[&str], constructor: StorageConstructor) {
+    write().add_storage(names, constructor)
+}
+
+pub(crate) fn driver(name: &str) -> Option<DriverConstructor> {
+    read()
+        .drivers
+        .get(&name.to_lowercase())
+        .map(|(_, constructor)| *constructor)
+}
+
+pub(crate) fn driver_meta(name: &str) -> Option<RunnerMeta<'static>> {
+    read()
+        .drivers
+        .get(&name.to_lowercase())
+        .map(|(meta, _)| meta.clone())
+}
+
+pub(crate) fn storage(name: &str) -> Option<StorageConstructor> {
+    read().storages.get(&name.to_lowercase()).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Configuration, DriverResult, InMemoryMigrations, MigrationStep, MigrationTemplate,
+        NamedDriver,
+    };
+
+    struct Custom {}
+
+    impl NamedDriver for Custom {
+        fn name() -> &'static str {
+            "custom"
+        }
+    }
+
+    impl Driver for Custom {
+        fn run(&mut self, _: &MigrationStep) -> Result<DriverResult, Error> {
+            Ok(DriverResult::Success)
+        }
+
+        fn render(&self, ms: &MigrationStep) -> Result<String, Error> {
+            Ok(ms.source.clone())
+        }
+
+        fn migration_template(&self) -> MigrationTemplate {
+            MigrationTemplate {
+                extension: "custom",
+                up: "",
+                down: None {},
+            }
+        }
+
+        fn meta(&self) -> RunnerMeta<'static> {
+            RunnerMeta {
+                name: "Custom",
+                desc: "Registered by the tests",
+                exts: vec!["custom"],
+            }
+        }
+    }
+
+    fn config(driver: &str) -> Result<Configuration, String> {
+        Configuration::load_from_str(&format!(
+            "---\nmitre:\n  _driver: {}\n  path: mitre.yml\nsomething:\n  _driver: {}\n",
+            driver, driver
+        ))
+        .map_err(|e| format!("{:?}", e))
+    }
+
+    #[test]
+    fn makes_registered_drivers_by_name_or_alias() -> Result<(), String> {
+        register_driver((Custom {}).meta(), &["Custom-Alias"], |_| {
+            Ok(Box::new(Custom {}))
+        });
+        for name in std::array::IntoIter::new(["custom", "CUSTOM", "custom-alias"]) {
+            let driver = crate::driver_from_config(&config(name)?, "something")
+                .map_err(|e| format!("{} {:?}", name, e))?;
+            assert_eq!(driver.meta().exts, vec!["custom"]);
+        }
+        assert_eq!(driver_meta("custom-alias").map(|m| m.name), Some("Custom"));
+        Ok(())
+    }
+
+    #[test]
+    fn makes_registered_storages() -> Result<(), String> {
+        register_storage(&["in-memory"], |_| Ok(Box::new(InMemoryMigrations::new())));
+        crate::migration_storage_from_config(&config("in-memory")?)
+            .map(|_| ())
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    #[test]
+    fn unregistered_drivers_are_unsupported() -> Result<(), String> {
+        match crate::driver_from_config(&config("nosql-of-the-week")?, "something") {
+            Err(Error::UnsupportedDriverSpecified) => Ok(()),
+            Err(e) => Err(format!("unexpected error {:?}", e)),
+            Ok(_) => Err(String::from("made a driver nobody registered")),
+        }
+    }
+}
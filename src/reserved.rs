@@ -49,28 +49,32 @@ pub enum ReservedWord<'a> {
 
 type RunnerName<'a> = &'a str;
 
-/// Const GNU "Bash3". **Currently not supported.**
+/// Const GNU "Bash3". Runs scripts with the configured `interpreter`, `bash` by default.
 pub const BASH_3: RunnerName = "Bash3";
-/// Const GNU "Bash4". **Currently not supported.**
+/// Const GNU "Bash4". Runs scripts with the configured `interpreter`, `bash` by default.
 pub const BASH_4: RunnerName = "Bash4";
-/// Const "HTTP".
+/// Const "HTTP". The method is the extension, `.get`, `.post`, `.patch` or `.delete`.
 pub const HTTP: RunnerName = "HTTP";
-/// Const "Elasticsearch". **Currently not supported.**
+/// Const "Elasticsearch". Requests in `.es` files, the configured `index` is `{{index}}` in templates.
 pub const ELASTICSEARCH: RunnerName = "Elasticsearch";
-/// Const "Kafka". **Currently not supported.**
+/// Const "Kafka". Topics, their configs and records in `.kafka` files.
 pub const KAFKA: RunnerName = "Kafka";
 /// Const "MariaDB". Reserve this along side MySQL
 pub const MARIA_DB: RunnerName = "MariaDB";
 /// Const "MySQL". Prefered over MariaDB due to commonness of usage.
 pub const MYSQL: RunnerName = "MySQL";
-/// Const "Python3". No Python 2 support is planned. **Currently not supported.**
+/// Const "Python3". No Python 2 support is planned. Scripts get the configuration as JSON on stdin.
 pub const PYTHON_3: RunnerName = "Python3";
-/// Const "Rails". Target the latest version of Rails. **Currently not supported.**
+/// Const "Rails". Target the latest version of Rails. Runs `.rb` files with `bin/rails runner` in the app's `path`.
 pub const RAILS: RunnerName = "Rails";
-/// Const "Redis". **Currently not supported.**
+/// Const "Redis". One command per line, in `.redis` files.
 pub const REDIS: RunnerName = "Redis";
-/// Const "PostgreSQL". **Currently not supported.**
+/// Const "PostgreSQL". Each `.sql` step runs in a transaction of its own, it can also store Mitre's state.
 pub const POSTGRESQL: RunnerName = "Postgres";
+/// Const "SQLite". File based, configured with a `path` rather than a server.
+pub const SQLITE: RunnerName = "SQLite";
+/// Const "File". A YAML or JSON file configured with a `path`, stores state only, it runs nothing.
+pub const FILE: RunnerName = "File";
 
 /// Return all words in a `Vec<ReservedWord>` of enums.
 pub fn words<'a>() -> Vec<ReservedWord<'a>> {
@@ -125,16 +129,21 @@ pub fn words<'a>() -> Vec<ReservedWord<'a>> {
         desc: "Python 3",
         exts: vec!["py", "py3"],
     }),
+    ReservedWord::Runner(RunnerMeta {
+      name: SQLITE,
+      desc: "SQLite, the file based database",
+      exts: vec!["sql"],
+    }),
+    ReservedWord::Runner(RunnerMeta {
+      name: FILE,
+      desc: "A YAML or JSON file, for storing state only",
+      exts: vec![],
+    }),
     ReservedWord::Runner(RunnerMeta {
       name: KAFKA,
       desc: "Kafka",
       exts: vec!["kafka"],
     }),
-    ReservedWord::Runner(RunnerMeta {
-      name: POSTGRESQL,
-      desc: "PostgreSQL",
-      exts: vec!["sql"],
-    }),
     ReservedWord::Flag(Flag{
       name: "data",
       meaning: "This is a data migration affecting data only, not structure." 
@@ -150,6 +159,18 @@ pub fn words<'a>() -> Vec<ReservedWord<'a>> {
   ]
 }
 
+/// All [`words`], and the runners of any driver plugins on the `PATH`. The
+/// plugins are started to describe themselves, so this is for listing only.
+pub fn words_and_plugins<'a>() -> Vec<ReservedWord<'a>> {
+    let mut words = words();
+    words.extend(
+        crate::plugin::runners()
+            .into_iter()
+            .map(ReservedWord::Runner),
+    );
+    words
+}
+
 pub fn flags() -> impl Iterator<Item = Flag> {
     words().into_iter().filter_map(|word| match word {
         ReservedWord::Flag(f) => Some(f),
@@ -198,6 +219,11 @@ mod tests {
         assert!(runners().any(|v| v.name == "HTTP"));
     }
 
+    #[test]
+    fn test_runners_are_listed_once() {
+        assert!(runners().map(|r| r.name).all_unique());
+    }
+
     #[test]
     fn test_flags_from_string_flags() {
         let flags: Vec<Flag> = words()
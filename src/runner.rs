@@ -1,14 +1,7 @@
-use super::{Migration, MigrationStep, RunnerMeta};
-
-#[cfg(feature = "runner_mysql")]
-pub mod mysql;
-#[cfg(feature = "runner_postgres")]
-pub mod postgresql;
-
-/// [`Runner`] specific configuration, there is
+/// [`crate::Driver`] specific configuration, there is
 /// also  [`crate::config::Configuration`] which holds
 /// the global configuration.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Clone, Default)]
 pub struct Configuration {
     // Runner is not optional, but we need to option it here to maintain
     // serde::Deserialize compatibility
@@ -30,78 +23,48 @@ pub struct Configuration {
 
     pub username: Option<String>,
     pub password: Option<String>,
-}
 
-#[derive(Debug)]
-pub enum Error {
-    #[cfg(feature = "runner_mysql")]
-    MySql(::mysql::Error),
-
-    #[cfg(feature = "runner_postgres")]
-    PostgreSql(postgres::error::Error),
-
-    /// No configuration provided for the runner, which is a problem
-    NoConfigForRunner {
-        name: String,
-    },
-
-    // Attempted to use the wrong runner/config combo
-    RunnerNameMismatch {
-        expected: String,
-        found: String,
-    },
-
-    /// Some runners need a database name to be provided (typically RDBMS) for flexibility
-    /// including the ability to create databases in migrations, that database is tentatively
-    /// selected and we won't fail until the very last moment that we need to select the database
-    /// but cannot.
-    CouldNotSelectDatabase,
-
-    /// Could not get a runner
-    CouldNotGetRunner {
-        reason: String,
-    },
-
-    /// Template error such as a syntax error.
-    Template {
-        reason: String,
-        template: mustache::Template,
-    },
-
-    /// TODO: Describe these
-    RunningMigration {
-        cause: String,
-    },
+    pub path: Option<String>, // used by SQLite, File, Rails (the app's directory)
 
-    /// We successfully ran the migration, but we didn't succeed in
-    /// recording the status
-    RecordingMigrationResult {
-        cause: String,
-    },
+    pub protocol: Option<String>, // used by HTTP, ElasticSearch (http, or https)
+
+    pub interpreter: Option<String>, // used by Bash, Python, Rails, e.g "bash -eu" or "bundle exec rails runner"
 
-    // Couldn't make a runner from the config
-    CouldNotFindOrCreateRunner {
-        config_name: String,
-    },
+    pub virtualenv: Option<String>, // used by Python, when no interpreter is given
 
-    /// Migrations may not contain both "up" and "change"
-    MigrationContainsBothUpAndChange(Migration),
+    pub timeout: Option<u32>, // used by Bash, Python, Rails, in seconds, per migration
 
-    MigrationHasFailed(String, Migration),
+    pub rails_env: Option<String>, // used by Rails, exported as RAILS_ENV
 }
 
-#[cfg(feature = "runner_mysql")]
-impl From<::mysql::Error> for Error {
-    fn from(err: ::mysql::Error) -> Error {
-        Error::MySql(err)
+impl Configuration {
+    // Values which aren't set are null, so that scripts and plugins can
+    // index the object without checking for every key first.
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "_driver": self._driver,
+            "database": self.database,
+            "database_number": self.database_number,
+            "index": self.index,
+            "ip_or_hostname": self.ip_or_hostname,
+            "port": self.port,
+            "username": self.username,
+            "password": self.password,
+            "path": self.path,
+            "protocol": self.protocol,
+            "interpreter": self.interpreter,
+            "virtualenv": self.virtualenv,
+            "timeout": self.timeout,
+            "rails_env": self.rails_env,
+        })
     }
 }
 
-#[cfg(feature = "runner_postgres")]
-impl From<postgres::error::Error> for Error {
-    fn from(err: postgres::error::Error) -> Error {
-        Error::PostgreSql(err)
-    }
+#[derive(Debug)]
+pub enum Error {
+    /// We successfully ran the migration, but we didn't succeed in
+    /// recording the status
+    RecordingMigrationResult { cause: String },
 }
 
 impl std::fmt::Display for Error {
@@ -116,6 +79,9 @@ pub enum MigrationState {
     Orphaned,
 
     FilteredOut,
+
+    // Applied, but the source on disk no longer matches the stored source.
+    Modified,
 }
 
 impl std::fmt::Display for MigrationState {
@@ -125,6 +91,7 @@ impl std::fmt::Display for MigrationState {
             MigrationState::Applied => write!(f, "Applied"),
             MigrationState::Orphaned => write!(f, "Orphaned"),
             MigrationState::FilteredOut => write!(f, "Filtered Out"),
+            MigrationState::Modified => write!(f, "Modified"),
         }
     }
 }
@@ -138,75 +105,3 @@ pub enum MigrationResult {
     IrreversibleMigration, // migration contains no "down" part.
     SkippedDueToEarlierError,
 }
-
-/// A Boxed runner helps us with the trait objects
-/// and is practically a first-class citizen.
-pub type BoxedRunner = Box<dyn Runner>;
-
-/// Analog the `from_config` in MigrationStore trait, which however does
-/// not box the StateStore result. Takes a runner::Configuration and
-/// obeys the currently compiled in features to ensure that we have a single
-/// point runner factory.
-///
-/// In this place synonyms are taken taken for the runner drivers.
-pub fn from_config(
-    c: &crate::config::Configuration,
-    config_name: &str,
-) -> Result<BoxedRunner, Error> {
-    log::debug!(
-        "Searching for runner {:?} in configured runners {:?}",
-        config_name,
-        c.configured_drivers.keys(),
-    );
-
-    let rc = c
-        .configured_drivers
-        .get(config_name)
-        .ok_or(Error::NoConfigForRunner {
-            name: config_name.to_string(),
-        })?;
-
-    #[cfg(feature = "runner_mysql")]
-    log::trace!(
-        "comparing {} to {} and {}",
-        rc._driver.to_lowercase(),
-        crate::reserved::MYSQL.to_lowercase(),
-        crate::reserved::MARIA_DB.to_lowercase()
-    );
-    if rc._driver.to_lowercase() == crate::reserved::MYSQL.to_lowercase()
-        || rc._driver.to_lowercase() == crate::reserved::MARIA_DB.to_lowercase()
-    {
-        log::info!("matched, returning a MySQL runner");
-        return Ok(Box::new(mysql::runner::MySql::new_runner(rc.clone())?));
-    }
-    #[cfg(feature = "runner_postgres")]
-    if rc._driver.to_lowercase() == crate::reserved::POSTGRESQL.to_lowercase() {
-        return Ok(Box::new(postgresql::PostgreSql::new_runner(rc.clone())?));
-    }
-    log::error!(
-        "There seems to be no avaiable (not compiled, not enabled) runner for {} (runner: {})",
-        config_name,
-        rc._driver,
-    );
-    Err(Error::CouldNotFindOrCreateRunner {
-        config_name: config_name.to_string(),
-    })
-}
-
-pub type MigrationTemplate = &'static str;
-pub type MigrationFileExtension = &'static str;
-
-pub trait Runner {
-    fn new_runner(config: Configuration) -> Result<Self, Error>
-    where
-        Self: Sized;
-
-    fn apply(&mut self, _: &MigrationStep) -> Result<(), Error>;
-
-    /// Returns tuple with up, down and file extension for the migration
-    fn migration_template(&self) -> (MigrationTemplate, MigrationTemplate, MigrationFileExtension);
-
-    /// Provides metadata about this runner. Each runner implementation
-    /// must implement this.
-    fn meta(&self) -> RunnerMeta;
-}
@@ -0,0 +1,223 @@
+use super::Error;
+use log::{info, warn};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+// How often a running script is checked on, when there is a timeout
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Splits an `interpreter` setting into the program and its own arguments,
+// e.g "bash -eu" or "/opt/venv/bin/python -X dev"
+pub(crate) fn command(interpreter: &str) -> Command {
+    let mut words = interpreter.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or(interpreter));
+    command.args(words);
+    command
+}
+
+// Names the header setting a migration's own timeout, in seconds, e.g
+// "# mitre-timeout: 3600"
+const TIMEOUT_HEADER: &str = "mitre-timeout:";
+
+/// The timeout for a script migration, either from a `# mitre-timeout: <seconds>`
+/// line among the comments at the top of its `source`, or the `configured`
+/// one (in seconds), so that a long backfill and a quick fix sharing a
+/// configuration can have different limits.
+pub(crate) fn timeout(source: &str, configured: Option<u32>) -> Result<Option<Duration>, Error> {
+    let header = source
+        .lines()
+        .map(str::trim)
+        .take_while(|line| line.is_empty() || line.starts_with('#'))
+        .filter_map(|line| {
+            line.trim_start_matches('#')
+                .trim()
+                .strip_prefix(TIMEOUT_HEADER)
+        })
+        .next();
+    let seconds = match header {
+        Some(value) => match value.trim().parse::<u32>() {
+            Ok(seconds) => Some(seconds),
+            Err(e) => {
+                return Err(Error::QueryFailed {
+                    reason: None {},
+                    msg: format!(
+                        "{} {:?} is not a number of seconds: {}",
+                        TIMEOUT_HEADER,
+                        value.trim(),
+                        e
+                    ),
+                })
+            }
+        },
+        None => configured,
+    };
+    Ok(seconds.map(|s| Duration::from_secs(s.into())))
+}
+
+fn read_all<R: Read + Send + 'static>(pipe: Option<R>) -> std::thread::JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut buf = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        String::from_utf8_lossy(&buf).into_owned()
+    })
+}
+
+/// Writes a rendered script to a temporary file for its interpreter to run,
+/// rather than passing it as an argument, which may be no longer than 128KiB
+/// on Linux. The file is removed when the returned handle is dropped.
+#[cfg(any(feature = "runner_bash", feature = "runner_python"))]
+pub(crate) fn script_file(script: &str) -> Result<tempfile::NamedTempFile, Error> {
+    let mut file = tempfile::NamedTempFile::new().map_err(Error::Io)?;
+    file.write_all(script.as_bytes()).map_err(Error::Io)?;
+    file.flush().map_err(Error::Io)?;
+    Ok(file)
+}
+
+/// Runs a script migration's `command` to completion, writing `stdin` (if
+/// any) to it first. Returns the script's stdout and stderr, or
+/// `Error::ScriptFailed` if it exits non-zero. With a `timeout` the script
+/// is killed once it has run that long, with `Error::ScriptTimedOut`.
+pub(crate) fn run(
+    mut command: Command,
+    path: &Path,
+    stdin: Option<&[u8]>,
+    timeout: Option<Duration>,
+) -> Result<(String, String), Error> {
+    let mut child = command
+        .stdin(match stdin {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(Error::Io)?;
+
+    // Read both pipes as the script runs, so that it can't block on a full
+    // pipe while we wait for it to exit.
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        // Scripts needn't read their input, and may have exited already
+        match pipe.write_all(input) {
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => {
+                warn!("writing to stdin of {}: {}", path.display(), e)
+            }
+            _ => {}
+        }
+    }
+
+    let started = Instant::now();
+    let status = loop {
+        match (child.try_wait().map_err(Error::Io)?, timeout) {
+            (Some(status), _) => break status,
+            (None, None) => break child.wait().map_err(Error::Io)?,
+            (None, Some(timeout)) if started.elapsed() >= timeout => {
+                warn!("killing {} after {:?}", path.display(), timeout);
+                let _ = child.kill();
+                let _ = child.wait();
+                // The readers are left behind, anything the script started
+                // may still hold its pipes open.
+                return Err(Error::ScriptTimedOut {
+                    path: path.to_path_buf(),
+                    timeout,
+                });
+            }
+            (None, Some(_)) => std::thread::sleep(POLL_INTERVAL),
+        }
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        return Err(Error::ScriptFailed {
+            path: path.to_path_buf(),
+            exit_code: status.code(),
+            stdout,
+            stderr,
+        });
+    }
+    Ok((stdout, stderr))
+}
+
+/// Logs what a script migration printed, once it has succeeded, so that e.g.
+/// the progress of a backfill isn't lost. (When it fails the output is in
+/// `Error::ScriptFailed`.)
+pub(crate) fn log_output(path: &Path, stdout: &str, stderr: &str) {
+    for line in stdout.lines() {
+        info!("{}: {}", path.display(), line);
+    }
+    for line in stderr.lines() {
+        info!("{} (stderr): {}", path.display(), line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_the_timeout_in_the_header() -> Result<(), String> {
+        let source = "#!/usr/bin/env python3\n\n# backfill\n#  mitre-timeout: 3600\nprint()\n";
+        assert_eq!(
+            timeout(source, Some(10)).map_err(|e| format!("{:?}", e))?,
+            Some(Duration::from_secs(3600))
+        );
+        // Only the leading comments are the header
+        let source = "print()\n# mitre-timeout: 3600\n";
+        assert_eq!(
+            timeout(source, Some(10)).map_err(|e| format!("{:?}", e))?,
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            timeout(source, None {}).map_err(|e| format!("{:?}", e))?,
+            None {}
+        );
+        match timeout("# mitre-timeout: an hour\n", Some(10)) {
+            Err(Error::QueryFailed { msg, .. }) => assert!(msg.contains("an hour")),
+            r => return Err(format!("unexpected {:?}", r)),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn kills_scripts_which_run_past_the_timeout() -> Result<(), String> {
+        let mut sleep = command("sh -c");
+        sleep.arg("exec sleep 5");
+        let started = Instant::now();
+        match run(
+            sleep,
+            Path::new("slow.sh"),
+            None {},
+            Some(Duration::from_millis(100)),
+        ) {
+            Err(Error::ScriptTimedOut { timeout, .. }) => {
+                assert_eq!(timeout, Duration::from_millis(100))
+            }
+            Err(e) => return Err(format!("unexpected error {:?}", e)),
+            Ok(_) => return Err(String::from("the script was not killed")),
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+        Ok(())
+    }
+
+    #[test]
+    fn writes_stdin_and_reads_output() -> Result<(), String> {
+        let mut cat = command("sh -c");
+        cat.arg("cat; echo done >&2");
+        let (stdout, stderr) = run(
+            cat,
+            Path::new("cat.sh"),
+            Some(b"{\"a\": 1}"),
+            Some(Duration::from_secs(5)),
+        )
+        .map_err(|e| format!("{:?}", e))?;
+        assert_eq!(stdout, "{\"a\": 1}");
+        assert_eq!(stderr, "done\n");
+        Ok(())
+    }
+}
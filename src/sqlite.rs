@@ -0,0 +1,649 @@
+use chrono::prelude::*;
+use rusqlite::{params, Connection};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::{
+    driver,
+    migrations::{Direction, MigrationStep},
+    Driver, DriverResult, Error, HistoryEntry, Migration, MigrationList, MigrationStorage,
+    MigrationTemplate, NamedDriver, RunnerConfiguration, RunnerMeta,
+};
+use indoc::indoc;
+use log::{debug, info, trace};
+
+use crate::migrations::FORMAT_STR;
+
+const MIGRATION_STATE_TABLE_NAME: &str = "mitre_migration_state";
+const MIGRATION_STEPS_TABLE_NAME: &str = "mitre_migration_steps";
+const MIGRATION_HISTORY_TABLE_NAME: &str = "mitre_migration_history";
+
+/// A file based database, for local development and CI where there is no
+/// database server to hand. It can both run `.sql` migrations and store
+/// Mitre's state.
+pub struct Sqlite {
+    conn: Connection,
+    path: String,
+    // A connection to a database beside the real one, holding an exclusive
+    // transaction for as long as the storage is locked
+    lock: Option<Connection>,
+}
+
+// The rusqlite crate's errors cannot be carried in Error::QueryFailed, which
+// is tied to the mysql crate, so keep their description in the message.
+fn query_failed(e: rusqlite::Error, msg: &str) -> Error {
+    Error::QueryFailed {
+        reason: None {},
+        msg: format!("{}: {}", msg, e),
+    }
+}
+
+// Versions are stored as an INTEGER, as a BIGINT in MySQL
+fn version_number(version: &str) -> Result<i64, Error> {
+    version.parse::<i64>().map_err(|e| Error::QueryFailed {
+        reason: None {},
+        msg: format!("version {:?} is not a number: {}", version, e),
+    })
+}
+
+// The version of a stored migration is its timestamp
+fn version_date_time(version: i64) -> Result<NaiveDateTime, Error> {
+    NaiveDateTime::parse_from_str(&version.to_string(), FORMAT_STR).map_err(|e| {
+        Error::QueryFailed {
+            reason: None {},
+            msg: format!("stored migration {} has an invalid version: {}", version, e),
+        }
+    })
+}
+
+// Dates are stored in RFC3339, `row` names the row they were read from
+fn parse_date_time(s: &str, row: &str) -> Result<DateTime<Utc>, Error> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|date_time| date_time.with_timezone(&Utc))
+        .map_err(|e| Error::QueryFailed {
+            reason: None {},
+            msg: format!("{} has an invalid date {:?}: {}", row, s, e),
+        })
+}
+
+fn format_date_time(date_time: &DateTime<Utc>) -> String {
+    date_time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+impl Sqlite {
+    pub fn new(config: RunnerConfiguration) -> Result<Self, Error> {
+        let path = match &config.path {
+            Some(path) => path.clone(),
+            None => return Err(Error::ConfigurationIncomplete),
+        };
+        info!("opening sqlite database {:?}", path);
+        Ok(Sqlite {
+            conn: match Connection::open(&path) {
+                Ok(conn) => conn,
+                Err(e) => {
+                    return Err(Error::ConnectionError {
+                        msg: format!("Opening SQLite database {}: {}", path, e),
+                    })
+                }
+            },
+            path,
+            lock: None {},
+        })
+    }
+
+    fn bootstrap_migrations(&self) -> Vec<Migration> {
+        log::trace!("getting bootstrap migrations");
+        vec![
+            Migration {
+                date_time: chrono::Utc::now().naive_utc(),
+                built_in: true,
+                flags: vec![],
+                configuration_name: String::from("mitre"),
+                steps: std::array::IntoIter::new([
+                    (
+                        Direction::Up,
+                        MigrationStep {
+                            path: PathBuf::from("built/in/migration"),
+                            source: String::from(include_str!(
+                                "migrations/bootstrap_sqlite_migration_storage.sql"
+                            )),
+                        },
+                    ),
+                    (
+                        Direction::Down,
+                        MigrationStep {
+                            path: PathBuf::from("built/in/migration"),
+                            source: String::from(
+                                "DROP TABLE IF EXISTS {{migration_steps_table}}; DROP TABLE IF EXISTS {{migrations_table}};",
+                            ),
+                        },
+                    ),
+                ])
+                .collect(),
+            },
+            Migration {
+                date_time: chrono::Utc::now().naive_utc(),
+                built_in: true,
+                flags: vec![],
+                configuration_name: String::from("mitre"),
+                steps: std::array::IntoIter::new([
+                    (
+                        Direction::Up,
+                        MigrationStep {
+                            path: PathBuf::from("built/in/migration"),
+                            source: String::from(include_str!(
+                                "migrations/bootstrap_sqlite_migration_history.sql"
+                            )),
+                        },
+                    ),
+                    (
+                        Direction::Down,
+                        MigrationStep {
+                            path: PathBuf::from("built/in/migration"),
+                            source: String::from(
+                                "DROP TABLE IF EXISTS {{migration_history_table}};",
+                            ),
+                        },
+                    ),
+                ])
+                .collect(),
+            },
+        ]
+    }
+
+    fn template_ctx(&self) -> mustache::Data {
+        mustache::MapBuilder::new()
+            .insert_str("path", &self.path)
+            .insert_str("migrations_table", MIGRATION_STATE_TABLE_NAME)
+            .insert_str("migration_steps_table", MIGRATION_STEPS_TABLE_NAME)
+            .insert_str("migration_history_table", MIGRATION_HISTORY_TABLE_NAME)
+            .build()
+    }
+
+    fn render_statements(&self, query: &str) -> Result<String, Error> {
+        driver::render_template(query, &self.template_ctx(), "queries")
+    }
+
+    // Statements does not imply _prepared_ statements
+    // in the name because a &str may contain multiple expressions
+    fn apply_statements(&mut self, query: &str) -> Result<(), Error> {
+        let q = self.render_statements(query)?;
+        match self.conn.execute_batch(&q) {
+            Ok(_) => {
+                info!("query {:?} ran successfully", q);
+                Ok(())
+            }
+            Err(e) => Err(query_failed(e, "Could not run the sqlite query")),
+        }
+    }
+
+    fn bootstrap(&mut self) -> Result<(), Error> {
+        debug!("bootstrapping sqlite migration storage");
+        for bootstrap_migration in self.bootstrap_migrations().iter() {
+            trace!("applying {:?}", bootstrap_migration);
+            self.apply(bootstrap_migration)?;
+        }
+        Ok(())
+    }
+
+    fn is_bootstrapped(&mut self) -> Result<bool, Error> {
+        self.conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?)",
+                params![MIGRATION_STATE_TABLE_NAME],
+                |row| row.get(0),
+            )
+            .map_err(|e| query_failed(e, "Checking for SQLite table existance"))
+    }
+}
+
+impl Driver for Sqlite {
+    fn run(&mut self, ms: &MigrationStep) -> Result<DriverResult, Error> {
+        self.apply_statements(&ms.source)?;
+        Ok(DriverResult::Success)
+    }
+
+    fn render(&self, ms: &MigrationStep) -> Result<String, Error> {
+        self.render_statements(&ms.source)
+    }
+
+    fn migration_template(&self) -> MigrationTemplate {
+        MigrationTemplate {
+            extension: "sql",
+            up: indoc!(
+                "
+                -- Put your migration here
+                CREATE TABLE your_table (
+                    column_one TEXT NOT NULL
+                );
+                "
+            ),
+            down: Some(indoc!(
+                "
+                DROP TABLE your_table;
+                "
+            )),
+        }
+    }
+
+    fn meta(&self) -> RunnerMeta<'static> {
+        crate::reserved::runner_by_name(crate::reserved::SQLITE).expect("reserved word not found")
+    }
+}
+
+impl NamedDriver for Sqlite {
+    fn name() -> &'static str {
+        "sqlite"
+    }
+}
+
+impl MigrationList for Sqlite {
+    fn all(&mut self) -> Result<Box<(dyn Iterator<Item = Migration> + 'static)>, Error> {
+        if !self.is_bootstrapped()? {
+            info!("early return with empty migration list, we appear not to be initialized");
+            return Ok(Box::new(vec![].into_iter()));
+        }
+
+        let q = format!(
+            "SELECT version, flags, configuration_name, built_in FROM {t} ORDER BY version ASC",
+            t = MIGRATION_STATE_TABLE_NAME
+        );
+        let rows = self
+            .conn
+            .prepare(&q)
+            .and_then(|mut stmt| {
+                stmt.query_map(params![], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, bool>(3)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, rusqlite::Error>>()
+            })
+            .map_err(|e| query_failed(e, "Querying list of SQLite stored migrations"))?;
+        let mut migrations = rows
+            .into_iter()
+            .map(|(version, flags, configuration_name, built_in)| {
+                Ok(Migration {
+                    date_time: version_date_time(version)?,
+                    flags: crate::reserved::flags_from_str_flags(&flags),
+                    configuration_name,
+                    built_in,
+                    steps: std::collections::HashMap::new(),
+                })
+            })
+            .collect::<Result<Vec<Migration>, Error>>()?;
+
+        let q = format!(
+            "SELECT direction, source, path FROM {t} WHERE version = ?",
+            t = MIGRATION_STEPS_TABLE_NAME
+        );
+        for m in &mut migrations {
+            let version = version_number(&m.version())?;
+            let steps = self
+                .conn
+                .prepare(&q)
+                .and_then(|mut stmt| {
+                    stmt.query_map(params![version], |row| {
+                        Ok((
+                            Direction::from(row.get::<_, String>(0)?),
+                            MigrationStep {
+                                source: row.get(1)?,
+                                path: PathBuf::from(row.get::<_, String>(2)?),
+                            },
+                        ))
+                    })?
+                    .collect::<Result<Vec<(Direction, MigrationStep)>, rusqlite::Error>>()
+                })
+                .map_err(|e| query_failed(e, "Querying list of SQLite stored migration steps"))?;
+            for (direction, step) in steps {
+                m.steps.insert(direction, step);
+            }
+        }
+
+        Ok(Box::new(migrations.into_iter()))
+    }
+}
+
+impl MigrationStorage for Sqlite {
+    #[cfg(test)]
+    fn reset(&mut self) -> Result<(), Error> {
+        log::trace!("Running SQLite reset.");
+        for bootstrap_migration in self.bootstrap_migrations().iter().rev() {
+            self.unapply(bootstrap_migration)?;
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, m: Migration) -> Result<(), Error> {
+        trace!("add about to bootstrap");
+        self.bootstrap()?;
+
+        // Note, that transaction will be rolled back implicitly on Drop, if not committed.
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| query_failed(e, "could not start transaction"))?;
+        let q = indoc::formatdoc!(
+            "
+          REPLACE INTO {}
+          (
+            version,
+            stored_at,
+            flags,
+            configuration_name,
+            built_in
+          )
+          VALUES ( ?, ?, ?, ?, ? );",
+            MIGRATION_STATE_TABLE_NAME
+        );
+        let version = version_number(&m.version())?;
+        tx.execute(
+            &q,
+            params![
+                version,
+                format_date_time(&Utc::now()),
+                m.flags_as_string(),
+                m.configuration_name,
+                m.built_in,
+            ],
+        )
+        .map_err(|e| query_failed(e, "could not insert migration into migration state table"))?;
+        // Storing a migration again replaces its steps, rather than adding to them
+        tx.execute(
+            &format!(
+                "DELETE FROM {} WHERE version = ?",
+                MIGRATION_STEPS_TABLE_NAME
+            ),
+            params![version],
+        )
+        .map_err(|e| {
+            query_failed(
+                e,
+                "could not remove previous migration steps from migration steps table",
+            )
+        })?;
+        let q = indoc::formatdoc!(
+            "
+          INSERT INTO {}
+          (
+            version,
+            direction,
+            source,
+            path
+          )
+          VALUES ( ?, ?, ?, ? );",
+            MIGRATION_STEPS_TABLE_NAME
+        );
+        for (direction, s) in &m.steps {
+            tx.execute(
+                &q,
+                params![
+                    version,
+                    format!("{:?}", direction).to_lowercase(),
+                    s.source,
+                    s.path.to_string_lossy(),
+                ],
+            )
+            .map_err(|e| {
+                query_failed(
+                    e,
+                    "could not insert migration steps into migration steps table",
+                )
+            })?;
+        }
+        tx.commit()
+            .map_err(|e| query_failed(e, "could not commit transaction"))
+    }
+
+    fn remove(&mut self, m: Migration) -> Result<(), Error> {
+        trace!("remove about to bootstrap");
+        self.bootstrap()?;
+
+        let version = version_number(&m.version())?;
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| query_failed(e, "could not start transaction"))?;
+        // Steps first, they reference the migration
+        let mut removed = 0;
+        for table in &[MIGRATION_STEPS_TABLE_NAME, MIGRATION_STATE_TABLE_NAME] {
+            removed = tx
+                .execute(
+                    &format!("DELETE FROM {} WHERE version = ?", table),
+                    params![version],
+                )
+                .map_err(|e| query_failed(e, "could not remove migration"))?;
+        }
+        if removed == 0 {
+            return Err(Error::MigrationNotFound {
+                version: m.version(),
+            });
+        }
+        tx.commit()
+            .map_err(|e| query_failed(e, "could not commit transaction"))
+    }
+
+    // SQLite's own locks belong to a transaction, and the storage needs its
+    // transactions for itself, so the exclusive transaction is held on a
+    // database beside it instead. Should this process die the lock goes
+    // with it.
+    fn lock(&mut self, timeout: Duration) -> Result<(), Error> {
+        let lock_path = format!("{}.lock", self.path);
+        let lock = Connection::open(&lock_path).map_err(|e| Error::ConnectionError {
+            msg: format!("Opening SQLite lock database {}: {}", lock_path, e),
+        })?;
+        lock.busy_timeout(timeout)
+            .map_err(|e| query_failed(e, "could not set busy timeout"))?;
+        match lock.execute_batch("BEGIN EXCLUSIVE") {
+            Ok(_) => {
+                self.lock = Some(lock);
+                Ok(())
+            }
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == rusqlite::ErrorCode::DatabaseBusy =>
+            {
+                Err(Error::LockTimeout { timeout })
+            }
+            Err(e) => Err(query_failed(e, "could not acquire migration lock")),
+        }
+    }
+
+    fn unlock(&mut self) -> Result<(), Error> {
+        match self.lock.take() {
+            Some(lock) => lock
+                .execute_batch("COMMIT")
+                .map_err(|e| query_failed(e, "could not release migration lock")),
+            None => Ok(()),
+        }
+    }
+
+    fn record(&mut self, entry: HistoryEntry) -> Result<(), Error> {
+        // A failed attempt may well be the first time this storage is used
+        self.bootstrap()?;
+        let q = indoc::formatdoc!(
+            "
+          INSERT INTO {}
+          (
+            version,
+            direction,
+            started_at,
+            finished_at,
+            outcome,
+            error,
+            hostname,
+            mitre_version
+          )
+          VALUES ( ?, ?, ?, ?, ?, ?, ?, ? );",
+            MIGRATION_HISTORY_TABLE_NAME
+        );
+        let version = version_number(&entry.version)?;
+        self.conn
+            .execute(
+                &q,
+                params![
+                    version,
+                    format!("{:?}", entry.direction).to_lowercase(),
+                    format_date_time(&entry.started_at),
+                    format_date_time(&entry.finished_at),
+                    entry.outcome,
+                    entry.error,
+                    entry.hostname,
+                    entry.mitre_version,
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| query_failed(e, "could not insert into migration history table"))
+    }
+
+    fn history(&mut self) -> Result<Vec<HistoryEntry>, Error> {
+        self.bootstrap()?;
+        let q = format!(
+            "SELECT version, direction, started_at, finished_at, outcome, error, hostname, mitre_version FROM {t} ORDER BY id ASC",
+            t = MIGRATION_HISTORY_TABLE_NAME
+        );
+        let rows = self
+            .conn
+            .prepare(&q)
+            .and_then(|mut stmt| {
+                stmt.query_map(params![], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                        row.get(6)?,
+                        row.get(7)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, rusqlite::Error>>()
+            })
+            .map_err(|e| query_failed(e, "Querying the SQLite migration history"))?;
+        rows.into_iter()
+            .map(
+                |(
+                    version,
+                    direction,
+                    started_at,
+                    finished_at,
+                    outcome,
+                    error,
+                    hostname,
+                    mitre_version,
+                )| {
+                    let row = format!("migration history entry of {} ({})", version, direction);
+                    Ok(HistoryEntry {
+                        version: version.to_string(),
+                        direction: Direction::from(direction),
+                        started_at: parse_date_time(&started_at, &row)?,
+                        finished_at: parse_date_time(&finished_at, &row)?,
+                        outcome,
+                        error,
+                        hostname,
+                        mitre_version,
+                    })
+                },
+            )
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migration_storage::tests::{test_sqlite_storage, test_sqlite_storage_configuration};
+
+    fn migration(steps: &[(Direction, &str)]) -> Migration {
+        Migration {
+            date_time: NaiveDateTime::parse_from_str("20210101120000", FORMAT_STR).unwrap(),
+            steps: steps
+                .iter()
+                .map(|(direction, source)| {
+                    (
+                        direction.clone(),
+                        MigrationStep {
+                            path: PathBuf::from(format!("{:?}.sql", direction)),
+                            source: String::from(*source),
+                        },
+                    )
+                })
+                .collect(),
+            built_in: false,
+            flags: vec![],
+            configuration_name: String::from("sqlite"),
+        }
+    }
+
+    #[test]
+    fn storing_a_migration_again_replaces_its_steps() -> Result<(), String> {
+        let mut sqlite = test_sqlite_storage();
+        sqlite
+            .add(migration(&[(Direction::Up, "a"), (Direction::Down, "b")]))
+            .map_err(|e| format!("{:?}", e))?;
+        sqlite
+            .add(migration(&[(Direction::Change, "c")]))
+            .map_err(|e| format!("{:?}", e))?;
+        let stored = sqlite
+            .all()
+            .map_err(|e| format!("{:?}", e))?
+            .collect::<Vec<Migration>>();
+        assert_eq!(stored, vec![migration(&[(Direction::Change, "c")])]);
+        Ok(())
+    }
+
+    #[test]
+    fn removing_from_an_empty_store_is_not_found() -> Result<(), String> {
+        let mut sqlite = test_sqlite_storage();
+        match sqlite.remove(migration(&[(Direction::Change, "c")])) {
+            Err(Error::MigrationNotFound { version }) => {
+                assert_eq!(version, "20210101120000");
+                Ok(())
+            }
+            Err(e) => Err(format!("unexpected error {:?}", e)),
+            Ok(_) => Err(String::from("removed a migration which was never stored")),
+        }
+    }
+
+    #[test]
+    fn history_names_entries_with_malformed_dates() -> Result<(), String> {
+        let dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+        let config = test_sqlite_storage_configuration(dir.path());
+        let path = config.path.clone().unwrap_or_default();
+        let mut sqlite = Sqlite::new(config).map_err(|e| format!("{:?}", e))?;
+        sqlite
+            .record(HistoryEntry {
+                version: String::from("20210101120000"),
+                direction: Direction::Up,
+                started_at: Utc::now(),
+                finished_at: Utc::now(),
+                outcome: String::from("success"),
+                error: None {},
+                hostname: String::from("localhost"),
+                mitre_version: String::from("0.0.0"),
+            })
+            .map_err(|e| format!("{:?}", e))?;
+        Connection::open(&path)
+            .and_then(|conn| {
+                conn.execute(
+                    &format!(
+                        "UPDATE {} SET started_at = 'yesterday'",
+                        MIGRATION_HISTORY_TABLE_NAME
+                    ),
+                    params![],
+                )
+            })
+            .map_err(|e| e.to_string())?;
+        match sqlite.history() {
+            Err(Error::QueryFailed { msg, .. }) => {
+                assert!(msg.contains("20210101120000 (up)"), "{}", msg);
+                Ok(())
+            }
+            Err(e) => Err(format!("unexpected error {:?}", e)),
+            Ok(_) => Err(String::from("read back a malformed date")),
+        }
+    }
+}
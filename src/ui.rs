@@ -1,7 +1,7 @@
 use crate::migrations::Migration;
 use crate::{
     config, migration_list_from_disk, migration_storage_from_config, Configuration, Engine,
-    MigrationList, MigrationStorage,
+    MigrationList, MigrationStorage, TagFilter,
 };
 use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Result};
 use askama::Template;
@@ -36,8 +36,12 @@ async fn index(data: web::Data<AppData>) -> Result<HttpResponse> {
 
     let mut v: Vec<MigrationTableRow> = Vec::new();
 
-    for (migration_state, m) in
-        Engine::diff(migration_list.deref_mut(), migration_storage.deref_mut()).expect("boom")
+    for (migration_state, m) in Engine::diff(
+        migration_list.deref_mut(),
+        migration_storage.deref_mut(),
+        &TagFilter::default(),
+    )
+    .expect("boom")
     {
         m.clone().steps.into_iter().for_each(|(direction, s)| {
             v.push(MigrationTableRow {
@@ -1,21 +1,28 @@
 const path = require("path");
 const mitre = require("../../src/ffi/node");
 
+const configPath = path.resolve(
+  __dirname,
+  "../fixtures/example-1-simple-mixed-migrations/mitre.yml"
+);
+
+// The state of the migration with a step of the given file name
+const stateOf = (res, fileName) =>
+  res.find(({ migration }) =>
+    migration.steps.some(s => path.basename(Object.values(s)[0].path) === fileName)
+  ).state;
+
 describe("diff", () => {
   it("fails with no config file passed", () => {
     expect(() => mitre.diff()).toThrow();
   });
 
   it("sends a list of migration states", () => {
-    let p = path.resolve(
-      __dirname,
-      "../fixtures/example-1-simple-mixed-migrations/mitre.yml"
-    );
-    let config = mitre.parseConfig(p)
+    let config = mitre.parseConfig(configPath)
     const res = mitre.diff(config);
     expect(res.length).toBe(4);
     for (let i = 0; i < res.length; i++) {
-      expect(res[i].state).toMatch(/Applied|Pending/g);
+      expect(res[i].state).toMatch(/Applied|Pending|FilteredOut/g);
       res[i].migration.steps.forEach(s => {
         expect(Object.keys(s).length).toEqual(1);
         expect(Object.keys(s)[0]).toMatch(/Up|Change|Down/g);
@@ -29,4 +36,13 @@ describe("diff", () => {
       // expect(res[i].migration.steps).toMatchSnapshot();
     }
   });
+
+  it("filters out long migrations unless their tag is included", () => {
+    const long = "20201208210038_create_some_random_table.long.es-mariadb.sql";
+    let config = mitre.parseConfig(configPath)
+    expect(stateOf(mitre.diff(config), long)).toBe("FilteredOut");
+    expect(stateOf(mitre.diff(config, { includeTags: ["long"] }), long)).toBe(
+      "Pending"
+    );
+  });
 });